@@ -0,0 +1,212 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write as _},
+    mem,
+    path::Path,
+};
+
+use crate::{
+    layout::CollisionEvent, readback::ReadbackRing, shaders::NO_INDEX, Sphere, MAX_SPHERES_COUNT,
+};
+
+// Ticks that can wait for their readback before new ones get dropped
+const SLOTS: usize = 8;
+// The event array follows the atomic counter at the alignment of the events
+const EVENTS_OFFSET: usize = 16;
+const WALLS: [&str; 9] = [
+    "max_x",
+    "min_x",
+    "max_y",
+    "min_y",
+    "max_z",
+    "min_z",
+    "player_paddle",
+    "opponent_paddle",
+    "ball",
+];
+
+// A sphere logs one collision with a wall, another sphere, a paddle or the
+// ball and one more for every primitive it touches in a tick. Room for all of
+// them would be a few megabytes copied every tick, so past this the events are
+// dropped and counted.
+const MAX_COLLISIONS_COUNT: usize = 4 * MAX_SPHERES_COUNT;
+pub const COLLISION_LOG_SIZE: usize =
+    EVENTS_OFFSET + mem::size_of::<CollisionEvent>() * MAX_COLLISIONS_COUNT;
+const SPHERES_SIZE: usize = mem::size_of::<Sphere>() * MAX_SPHERES_COUNT;
+
+// What a copy holds, the buffer has the spheres and then the collision log
+struct TickInfo {
+    tick: u32,
+    time: f64,
+    spheres_count: u32,
+}
+
+// Streams the spheres and the collisions of every tick to CSV files,
+// trajectories.csv and collisions.csv, a few frames behind the simulation
+pub struct AnalysisLog {
+    trajectories: BufWriter<File>,
+    collisions: BufWriter<File>,
+    readbacks: ReadbackRing<TickInfo>,
+    time: f64,
+    dropped: Dropped,
+}
+
+// What didn't make it into the files
+#[derive(Default)]
+pub struct Dropped {
+    // Ticks whose readback fell behind
+    pub ticks: u32,
+    // Collisions past the end of the log
+    pub collisions: u64,
+}
+
+impl AnalysisLog {
+    pub fn create(device: &wgpu::Device, directory: &Path) -> io::Result<Self> {
+        fs::create_dir_all(directory)?;
+        let mut trajectories = BufWriter::new(File::create(directory.join("trajectories.csv"))?);
+        writeln!(trajectories, "tick,time,sphere,x,y,z,vx,vy,vz")?;
+        let mut collisions = BufWriter::new(File::create(directory.join("collisions.csv"))?);
+        writeln!(
+            collisions,
+            "tick,time,sphere,other_sphere,wall,normal_x,normal_y,normal_z,impact_speed"
+        )?;
+        Ok(AnalysisLog {
+            trajectories,
+            collisions,
+            readbacks: create_readbacks(device),
+            time: 0.0,
+            dropped: Dropped::default(),
+        })
+    }
+
+    // Ticks still in flight on a lost device are gone
+    pub fn recreate_buffers(&mut self, device: &wgpu::Device) {
+        self.dropped.ticks += self.readbacks.in_flight() as u32;
+        self.readbacks = create_readbacks(device);
+    }
+
+    // Copies the buffers the physics pass of this tick wrote, has to be
+    // followed by `start_readback` once the encoder is submitted
+    pub fn copy(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        spheres_buffer: &wgpu::Buffer,
+        collision_buffer: &wgpu::Buffer,
+        spheres_count: u32,
+        tick: u32,
+        time_step: f32,
+    ) {
+        self.time += time_step as f64;
+        let info = TickInfo {
+            tick,
+            time: self.time,
+            spheres_count,
+        };
+        let Some(buffer) = self.readbacks.next(info) else {
+            self.dropped.ticks += 1;
+            return;
+        };
+        encoder.copy_buffer_to_buffer(
+            spheres_buffer,
+            0,
+            buffer,
+            0,
+            (mem::size_of::<Sphere>() * spheres_count as usize) as _,
+        );
+        encoder.copy_buffer_to_buffer(
+            collision_buffer,
+            0,
+            buffer,
+            SPHERES_SIZE as _,
+            COLLISION_LOG_SIZE as _,
+        );
+    }
+
+    pub fn start_readback(&mut self) {
+        self.readbacks.start_readback();
+    }
+
+    // Writes out every tick that has been read back
+    pub fn poll(&mut self) -> io::Result<()> {
+        let (trajectories, collisions) = (&mut self.trajectories, &mut self.collisions);
+        let dropped = &mut self.dropped.collisions;
+        self.readbacks.poll(|data, info| {
+            *dropped += write_tick(data, &info, trajectories, collisions)? as u64;
+            Ok(())
+        })
+    }
+
+    // Returns what was dropped on the way
+    pub fn finish(mut self) -> io::Result<Dropped> {
+        self.trajectories.flush()?;
+        self.collisions.flush()?;
+        self.dropped.ticks += self.readbacks.in_flight() as u32;
+        Ok(self.dropped)
+    }
+}
+
+fn create_readbacks(device: &wgpu::Device) -> ReadbackRing<TickInfo> {
+    ReadbackRing::new(device, SLOTS, (SPHERES_SIZE + COLLISION_LOG_SIZE) as _)
+}
+
+// Returns how many of the tick's collisions didn't fit in the log
+fn write_tick(
+    data: &[u8],
+    info: &TickInfo,
+    trajectories: &mut impl io::Write,
+    collisions: &mut impl io::Write,
+) -> io::Result<usize> {
+    let spheres = bytemuck::cast_slice::<u8, Sphere>(&data[..SPHERES_SIZE]);
+    for (index, sphere) in spheres[..info.spheres_count as usize].iter().enumerate() {
+        writeln!(
+            trajectories,
+            "{},{:.6},{},{},{},{},{},{},{}",
+            info.tick,
+            info.time,
+            index,
+            sphere.pos[0],
+            sphere.pos[1],
+            sphere.pos[2],
+            sphere.vel[0],
+            sphere.vel[1],
+            sphere.vel[2]
+        )?;
+    }
+    let log = &data[SPHERES_SIZE..];
+    let count = *bytemuck::from_bytes::<u32>(&log[..mem::size_of::<u32>()]) as usize;
+    // The counter keeps going past the end of the log
+    let logged = count.min(MAX_COLLISIONS_COUNT);
+    let mut events =
+        bytemuck::cast_slice::<u8, CollisionEvent>(&log[EVENTS_OFFSET..])[..logged].to_vec();
+    // The GPU appends them in whatever order the invocations ran
+    events.sort_by_key(|event| event.sphere);
+    let optional = |index: u32| {
+        if index == NO_INDEX {
+            String::new()
+        } else {
+            index.to_string()
+        }
+    };
+    // The primitives are numbered after the walls
+    let wall_name = |wall: u32| match WALLS.get(wall as usize) {
+        Some(name) => name.to_string(),
+        None if wall == NO_INDEX => String::new(),
+        None => format!("primitive_{}", wall as usize - WALLS.len()),
+    };
+    for event in events {
+        writeln!(
+            collisions,
+            "{},{:.6},{},{},{},{},{},{},{}",
+            event.tick,
+            info.time,
+            event.sphere,
+            optional(event.other),
+            wall_name(event.wall),
+            event.normal[0],
+            event.normal[1],
+            event.normal[2],
+            event.speed
+        )?;
+    }
+    Ok(count - logged)
+}
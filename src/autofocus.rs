@@ -0,0 +1,129 @@
+use std::{convert::Infallible, mem};
+
+use crate::readback::ReadbackRing;
+
+// Casts a single ray on the GPU to find the distance to whatever is under the
+// cursor, the result is read back asynchronously a few frames later.
+pub struct AutoFocus {
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+    result_buffer: wgpu::Buffer,
+    readbacks: ReadbackRing<()>,
+}
+
+impl AutoFocus {
+    pub fn new(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        scene_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let result_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: mem::size_of::<f32>() as _,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(mem::size_of::<f32>() as _),
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 1,
+                resource: result_buffer.as_entire_binding(),
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[scene_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &pipeline_layout, shader);
+        AutoFocus {
+            pipeline_layout,
+            pipeline,
+            bind_group,
+            result_buffer,
+            readbacks: ReadbackRing::new(device, 1, mem::size_of::<f32>() as _),
+        }
+    }
+
+    // For shader reloading, the pipeline is only replaced by `set_pipeline`
+    pub fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::ComputePipeline {
+        create_pipeline(device, &self.pipeline_layout, shader)
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: wgpu::ComputePipeline) {
+        self.pipeline = pipeline;
+    }
+
+    // Has to be followed by `start_readback` once the encoder is submitted,
+    // returns false if the previous request is still in flight
+    pub fn request(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        scene_bind_group: &wgpu::BindGroup,
+    ) -> bool {
+        let Some(readback_buffer) = self.readbacks.next(()) else {
+            return false;
+        };
+        {
+            let mut cpass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            cpass.set_pipeline(&self.pipeline);
+            cpass.set_bind_group(0, scene_bind_group, &[]);
+            cpass.set_bind_group(1, &self.bind_group, &[]);
+            cpass.dispatch_workgroups(1, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(
+            &self.result_buffer,
+            0,
+            readback_buffer,
+            0,
+            mem::size_of::<f32>() as _,
+        );
+        true
+    }
+
+    pub fn start_readback(&mut self) {
+        self.readbacks.start_readback();
+    }
+
+    pub fn poll(&mut self) -> Option<f32> {
+        let mut distance = None;
+        let Ok(()) = self.readbacks.poll(|data, ()| {
+            distance = Some(*bytemuck::from_bytes::<f32>(data));
+            Ok::<_, Infallible>(())
+        });
+        distance
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        module: shader,
+        entry_point: "cs_autofocus",
+    })
+}
@@ -0,0 +1,288 @@
+use pollster::FutureExt as _;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Write as _, fs, path::Path, time::Instant};
+use tracing::info;
+
+use crate::{
+    cli::{AdapterArgs, BenchArgs, ReportFormat, Resolution},
+    error::Error,
+    layout::PhysicsDiagnostics,
+    Renderer, RendererSettings,
+};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct Percentiles {
+    mean: f32,
+    p50: f32,
+    p95: f32,
+    p99: f32,
+}
+
+impl Percentiles {
+    fn new(mut samples: Vec<f32>) -> Self {
+        samples.sort_by(f32::total_cmp);
+        // Nearest rank
+        let percentile = |p: f32| {
+            let rank = (p / 100.0 * samples.len() as f32).ceil() as usize;
+            samples[rank.clamp(1, samples.len()) - 1]
+        };
+        Percentiles {
+            mean: samples.iter().sum::<f32>() / samples.len() as f32,
+            p50: percentile(50.0),
+            p95: percentile(95.0),
+            p99: percentile(99.0),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CaseReport {
+    spheres: u32,
+    resolution: Resolution,
+    cpu_ms: Percentiles,
+    gpu_ms: Percentiles,
+    // After the measured frames, missing from reports that predate it
+    #[serde(default)]
+    physics: Option<PhysicsDiagnostics>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BenchReport {
+    adapter: String,
+    backend: String,
+    // Either timestamp queries or submission to completion on the queue
+    gpu_timing: String,
+    seed: u64,
+    frames: u32,
+    warmup: u32,
+    time_step: f32,
+    cases: Vec<CaseReport>,
+}
+
+impl BenchReport {
+    fn load(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|err| format!("Can't read {}: {}", path.display(), err))?;
+        serde_json::from_str(&json).map_err(|err| {
+            format!(
+                "Can't parse {}, it has to be a JSON report: {}",
+                path.display(),
+                err
+            )
+        })
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "spheres,width,height,cpu_mean_ms,cpu_p50_ms,cpu_p95_ms,cpu_p99_ms,\
+             gpu_mean_ms,gpu_p50_ms,gpu_p95_ms,gpu_p99_ms,\
+             kinetic_energy,overlapping_pairs,max_penetration,spheres_outside\n",
+        );
+        for case in &self.cases {
+            let (cpu, gpu) = (case.cpu_ms, case.gpu_ms);
+            let physics = case.physics.map_or_else(
+                || ",,,".to_owned(),
+                |physics| {
+                    format!(
+                        "{},{},{},{}",
+                        physics.kinetic_energy,
+                        physics.overlapping_pairs,
+                        physics.max_penetration,
+                        physics.spheres_outside
+                    )
+                },
+            );
+            writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{},{},{},{},{}",
+                case.spheres,
+                case.resolution.width,
+                case.resolution.height,
+                cpu.mean,
+                cpu.p50,
+                cpu.p95,
+                cpu.p99,
+                gpu.mean,
+                gpu.p50,
+                gpu.p95,
+                gpu.p99,
+                physics
+            )
+            .expect("Can't format the report");
+        }
+        csv
+    }
+
+    // Prints the GPU time changes and returns the worst regression in percent
+    fn compare(&self, baseline: &BenchReport) -> f32 {
+        if baseline.adapter != self.adapter || baseline.gpu_timing != self.gpu_timing {
+            eprintln!(
+                "Baseline was measured on {} with {}, not {} with {}",
+                baseline.adapter, baseline.gpu_timing, self.adapter, self.gpu_timing
+            );
+        }
+        let mut worst = f32::NEG_INFINITY;
+        for case in &self.cases {
+            let Some(base) = baseline
+                .cases
+                .iter()
+                .find(|base| base.spheres == case.spheres && base.resolution == case.resolution)
+            else {
+                eprintln!(
+                    "{} spheres at {}: not in the baseline",
+                    case.spheres, case.resolution
+                );
+                continue;
+            };
+            let mut line = format!("{} spheres at {}:", case.spheres, case.resolution);
+            for (name, before, after) in [
+                ("p50", base.gpu_ms.p50, case.gpu_ms.p50),
+                ("p95", base.gpu_ms.p95, case.gpu_ms.p95),
+                ("p99", base.gpu_ms.p99, case.gpu_ms.p99),
+            ] {
+                // Nothing to take a percentage of
+                if before <= 0.0 {
+                    write!(
+                        line,
+                        " {} {:.3}ms -> {:.3}ms (no baseline time)",
+                        name, before, after
+                    )
+                    .expect("Can't format the comparison");
+                    continue;
+                }
+                let change = (after - before) / before * 100.0;
+                worst = worst.max(change);
+                write!(
+                    line,
+                    " {} {:.3}ms -> {:.3}ms ({:+.1}%)",
+                    name, before, after, change
+                )
+                .expect("Can't format the comparison");
+            }
+            eprintln!("{}", line);
+        }
+        worst
+    }
+}
+
+fn measure(
+    renderer: &mut Renderer,
+    args: &BenchArgs,
+) -> Result<(Percentiles, Percentiles), String> {
+    let mut cpu_times = Vec::with_capacity(args.frames as usize);
+    let mut gpu_times = Vec::with_capacity(args.frames as usize);
+    for frame in 0..args.warmup + args.frames {
+        let start = Instant::now();
+        renderer
+            .render(frame % 2 == 1)
+            .map_err(|err| err.to_string())?;
+        let cpu_time = start.elapsed().as_secs_f32() * 1000.0;
+        // Waiting for every frame keeps the GPU timings from overlapping
+        if !renderer
+            .device_loss
+            .poll(&renderer.device, wgpu::Maintain::Wait)
+        {
+            return Err("The device was lost".to_owned());
+        }
+        renderer.poll_readbacks();
+        let gpu_time = renderer
+            .gpu_frame_time
+            .take()
+            .ok_or_else(|| format!("No GPU time for frame {}", frame))?;
+        if frame >= args.warmup {
+            cpu_times.push(cpu_time);
+            gpu_times.push(gpu_time * 1000.0);
+        }
+    }
+    Ok((Percentiles::new(cpu_times), Percentiles::new(gpu_times)))
+}
+
+// One more step with the diagnostics, kept out of the measured frames
+fn diagnose(renderer: &mut Renderer, frame: u32) -> Result<Option<PhysicsDiagnostics>, String> {
+    renderer.diagnostics_interval = Some(1);
+    renderer.render_and_wait(frame % 2 == 1)?;
+    Ok(renderer.physics_diagnostics())
+}
+
+pub fn run(args: &BenchArgs, adapter: &AdapterArgs) -> Result<(), Error> {
+    // Before measuring, a bad path shouldn't throw the measurements away
+    let baseline = args
+        .baseline
+        .as_deref()
+        .map(BenchReport::load)
+        .transpose()
+        .map_err(Error::Baseline)?;
+    let mut report: Option<BenchReport> = None;
+    for &spheres in &args.spheres {
+        for &resolution in &args.resolutions {
+            let settings = RendererSettings {
+                spheres_count: spheres,
+                seed: Some(args.seed),
+                ..adapter.renderer_settings()
+            };
+            let mut renderer =
+                Renderer::new_headless(resolution.width, resolution.height, &settings)
+                    .block_on()?;
+            renderer.time_step = Some(args.time_step);
+            // Measure the requested resolution, not whatever the scaler settles on
+            renderer.resolution_scaler.enabled = false;
+            renderer.diagnostics_interval = None;
+            renderer.resize_render_targets();
+            let report = report.get_or_insert_with(|| BenchReport {
+                adapter: renderer.adapter_info.name.clone(),
+                backend: format!("{:?}", renderer.adapter_info.backend),
+                gpu_timing: if renderer.profiler.is_gpu() {
+                    "timestamp queries"
+                } else {
+                    "queue submission"
+                }
+                .to_owned(),
+                seed: args.seed,
+                frames: args.frames,
+                warmup: args.warmup,
+                time_step: args.time_step,
+                cases: Vec::new(),
+            });
+            let (cpu_ms, gpu_ms) = measure(&mut renderer, args).map_err(Error::Bench)?;
+            info!(
+                "{} spheres at {}: CPU p50 {:.3}ms, GPU p50 {:.3}ms",
+                spheres, resolution, cpu_ms.p50, gpu_ms.p50
+            );
+            let physics =
+                diagnose(&mut renderer, args.warmup + args.frames).map_err(Error::Bench)?;
+            if let Some(physics) = physics {
+                info!("{}", physics);
+            }
+            report.cases.push(CaseReport {
+                spheres,
+                resolution,
+                cpu_ms,
+                gpu_ms,
+                physics,
+            });
+        }
+    }
+    let Some(report) = report else {
+        return Ok(());
+    };
+    let output = match args.format {
+        ReportFormat::Json => {
+            serde_json::to_string_pretty(&report).expect("Can't serialize the report") + "\n"
+        }
+        ReportFormat::Csv => report.to_csv(),
+    };
+    match &args.output {
+        Some(path) => fs::write(path, output)
+            .map_err(|err| Error::Report(format!("{}: {}", path.display(), err)))?,
+        None => print!("{}", output),
+    }
+    if let Some(baseline) = baseline {
+        let worst = report.compare(&baseline);
+        if let Some(max_regression) = args.max_regression {
+            if worst > max_regression {
+                return Err(Error::Regression(worst, max_regression));
+            }
+        }
+    }
+    Ok(())
+}
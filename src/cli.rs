@@ -0,0 +1,328 @@
+use clap::{value_parser, Args, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use crate::{
+    RendererSettings, MAX_BOUNCES, MAX_RENDER_SCALE, MAX_SAMPLES_PER_PIXEL, MAX_SPHERES_COUNT,
+    MIN_RENDER_SCALE, TARGET_FPS,
+};
+
+#[derive(Parser)]
+#[command(version, about = "Ray traced bouncing spheres on the GPU")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub adapter: AdapterArgs,
+    /// Window size, e.g. 1920x1080
+    #[arg(long)]
+    pub resolution: Option<Resolution>,
+    /// Borderless fullscreen on the current monitor
+    #[arg(long)]
+    pub fullscreen: bool,
+    /// Fifo waits for vsync, Mailbox and Immediate don't
+    #[arg(long, value_enum, default_value_t = PresentMode::Fifo)]
+    pub present_mode: PresentMode,
+    /// Number of spheres
+    #[arg(long, default_value_t = MAX_SPHERES_COUNT as u32,
+          value_parser = value_parser!(u32).range(1..=MAX_SPHERES_COUNT as i64))]
+    pub spheres: u32,
+    /// Seed for the sphere positions, velocities and colors, random if not set
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Rays per pixel
+    #[arg(long, default_value_t = 4, value_parser = value_parser!(u32).range(1..=MAX_SAMPLES_PER_PIXEL as i64))]
+    pub samples: u32,
+    /// Reflection depth
+    #[arg(long, default_value_t = 5, value_parser = value_parser!(u32).range(1..=MAX_BOUNCES as i64))]
+    pub max_bounces: u32,
+    /// Start from a simulation state saved with F5
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// JSON file with planes, disks and boxes to put in the room, states bring their own
+    #[arg(long, conflicts_with = "state")]
+    pub scene: Option<PathBuf>,
+    /// Play ray-ten's game against the computer: keep the ball past your paddle from the near wall
+    #[arg(long)]
+    pub game: bool,
+    /// Record every input with the frame it was applied on, for `replay`
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+    /// Write the trajectories and collisions of every frame as CSV to this directory
+    #[arg(long)]
+    pub analysis: Option<PathBuf>,
+    /// Write the kinetic energy, momentum, overlaps and escaped spheres of every frame as CSV
+    #[arg(long)]
+    pub diagnostics: Option<PathBuf>,
+    /// Color grading LUT in the .cube format to use instead of the built-in one
+    #[arg(long)]
+    pub lut: Option<PathBuf>,
+    /// Frame rate dynamic resolution scales the render resolution to hold
+    #[arg(long, default_value_t = TARGET_FPS, value_parser = parse_fps)]
+    pub target_fps: f32,
+    /// Lowest render scale dynamic resolution goes down to, above 0 and at most 1
+    #[arg(long, default_value_t = MIN_RENDER_SCALE, value_parser = parse_render_scale)]
+    pub min_render_scale: f32,
+    /// Highest render scale dynamic resolution goes up to, above 0 and at most 1
+    #[arg(long, default_value_t = MAX_RENDER_SCALE, value_parser = parse_render_scale)]
+    pub max_render_scale: f32,
+    /// Reload the WGSL shaders from the source tree when they're saved
+    #[arg(long)]
+    pub watch_shaders: bool,
+    /// Print the available adapters and exit
+    #[arg(long)]
+    pub list_adapters: bool,
+}
+
+impl Cli {
+    pub fn renderer_settings(&self) -> RendererSettings {
+        RendererSettings {
+            spheres_count: self.spheres,
+            seed: self.seed,
+            samples_per_pixel: self.samples,
+            max_bounces: self.max_bounces,
+            color_lut: self.lut.clone(),
+            present_mode: self.present_mode.into(),
+            target_fps: self.target_fps,
+            min_render_scale: self.min_render_scale,
+            max_render_scale: self.max_render_scale,
+            ..self.adapter.renderer_settings()
+        }
+    }
+}
+
+fn parse_fps(s: &str) -> Result<f32, String> {
+    match s.parse() {
+        Ok(fps) if fps > 0.0 && f32::is_finite(fps) => Ok(fps),
+        _ => Err(format!("Expected a positive frame rate, got {:?}", s)),
+    }
+}
+
+fn parse_seconds(s: &str) -> Result<f32, String> {
+    match s.parse() {
+        Ok(seconds) if seconds > 0.0 && f32::is_finite(seconds) => Ok(seconds),
+        _ => Err(format!(
+            "Expected a positive number of seconds, got {:?}",
+            s
+        )),
+    }
+}
+
+fn parse_render_scale(s: &str) -> Result<f32, String> {
+    match s.parse() {
+        Ok(scale) if scale > 0.0 && scale <= 1.0 => Ok(scale),
+        _ => Err(format!(
+            "Expected a scale above 0 and at most 1, got {:?}",
+            s
+        )),
+    }
+}
+
+// Shared with the subcommands
+#[derive(Args)]
+pub struct AdapterArgs {
+    /// Only consider adapters of this backend
+    #[arg(long, value_enum, global = true)]
+    pub backend: Option<Backend>,
+    /// Use a software adapter
+    #[arg(long, global = true)]
+    pub fallback_adapter: bool,
+}
+
+impl AdapterArgs {
+    pub fn backends(&self) -> wgpu::Backends {
+        match self.backend {
+            Some(Backend::Vulkan) => wgpu::Backends::VULKAN,
+            Some(Backend::Metal) => wgpu::Backends::METAL,
+            Some(Backend::Dx12) => wgpu::Backends::DX12,
+            Some(Backend::Gl) => wgpu::Backends::GL,
+            None => wgpu::Backends::all(),
+        }
+    }
+
+    pub fn renderer_settings(&self) -> RendererSettings {
+        RendererSettings {
+            backends: self.backends(),
+            force_fallback_adapter: self.fallback_adapter,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PresentMode {
+    Fifo,
+    Mailbox,
+    Immediate,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(present_mode: PresentMode) -> Self {
+        match present_mode {
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Render fixed workloads headlessly and report frame time percentiles
+    Bench(BenchArgs),
+    /// Render a run at a fixed frame rate to PNG frames or a video
+    Export(ExportArgs),
+    /// Play back a session recorded with --record without a window
+    Replay(ReplayArgs),
+}
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// Seed for the sphere positions, velocities and colors
+    #[arg(long, default_value_t = 1)]
+    pub seed: u64,
+    /// Sphere counts to sweep over
+    #[arg(long, value_delimiter = ',', default_values_t = [100, 500, 1000],
+          value_parser = value_parser!(u32).range(1..=MAX_SPHERES_COUNT as i64))]
+    pub spheres: Vec<u32>,
+    /// Resolutions to sweep over
+    #[arg(long, value_delimiter = ',', default_values = ["1280x720", "1920x1080"])]
+    pub resolutions: Vec<Resolution>,
+    /// Measured frames per case
+    #[arg(long, default_value_t = 300, value_parser = value_parser!(u32).range(1..))]
+    pub frames: u32,
+    /// Frames rendered before measuring
+    #[arg(long, default_value_t = 60)]
+    pub warmup: u32,
+    /// Simulated seconds per frame, fixed so runs do the same work
+    #[arg(long, default_value_t = 1.0 / 60.0, value_parser = parse_seconds)]
+    pub time_step: f32,
+    #[arg(long, value_enum, default_value_t = ReportFormat::Json)]
+    pub format: ReportFormat,
+    /// Write the report to this file instead of stdout
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+    /// JSON report of an earlier run to compare against
+    #[arg(long)]
+    pub baseline: Option<PathBuf>,
+    /// Fail if any GPU percentile is this many percent slower than the baseline
+    #[arg(long, requires = "baseline")]
+    pub max_regression: Option<f32>,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Directory for the PNG frames or the video file to write
+    pub output: PathBuf,
+    #[arg(long, value_enum, default_value_t = ExportFormat::Png)]
+    pub format: ExportFormat,
+    /// Seed for the sphere positions, velocities and colors
+    #[arg(long, default_value_t = 1)]
+    pub seed: u64,
+    /// Start from a saved simulation state instead of the seed
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// JSON file with planes, disks and boxes to put in the room, states bring their own
+    #[arg(long, conflicts_with = "state")]
+    pub scene: Option<PathBuf>,
+    /// Number of spheres
+    #[arg(long, default_value_t = MAX_SPHERES_COUNT as u32,
+          value_parser = value_parser!(u32).range(1..=MAX_SPHERES_COUNT as i64))]
+    pub spheres: u32,
+    #[arg(long, default_value = "1920x1080")]
+    pub resolution: Resolution,
+    /// Frames per second of simulated time
+    #[arg(long, default_value_t = 60, value_parser = value_parser!(u32).range(1..))]
+    pub fps: u32,
+    /// Length of the run in seconds
+    #[arg(long, default_value_t = 10.0, value_parser = parse_seconds)]
+    pub duration: f32,
+    /// Rays per pixel, 16 unless --state has them
+    #[arg(long, value_parser = value_parser!(u32).range(1..=MAX_SAMPLES_PER_PIXEL as i64))]
+    pub samples: Option<u32>,
+    /// Reflection depth, 5 unless --state has it
+    #[arg(long, value_parser = value_parser!(u32).range(1..=MAX_BOUNCES as i64))]
+    pub max_bounces: Option<u32>,
+    /// Color grading LUT in the .cube format to use instead of the built-in one
+    #[arg(long)]
+    pub lut: Option<PathBuf>,
+    /// Encoder the raw frames are piped to for videos
+    #[arg(long, default_value = "ffmpeg")]
+    pub ffmpeg: PathBuf,
+    /// Write the trajectories and collisions of every frame as CSV to this directory
+    #[arg(long)]
+    pub analysis: Option<PathBuf>,
+    /// Write the kinetic energy, momentum, overlaps and escaped spheres of every frame as CSV
+    #[arg(long)]
+    pub diagnostics: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ReplayArgs {
+    /// Recording written by --record
+    pub input: PathBuf,
+    /// Directory to write every replayed frame to as a numbered PNG
+    #[arg(long)]
+    pub frames: Option<PathBuf>,
+    /// Save the simulation state after the last frame, to compare with the session
+    #[arg(long)]
+    pub save_state: Option<PathBuf>,
+    /// Write the trajectories and collisions of every frame as CSV to this directory
+    #[arg(long)]
+    pub analysis: Option<PathBuf>,
+    /// Write the kinetic energy, momentum, overlaps and escaped spheres of every frame as CSV
+    #[arg(long)]
+    pub diagnostics: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// Numbered PNG frames
+    Png,
+    /// Raw frames piped to ffmpeg, which picks the container from the file extension
+    Video,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Resolution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s
+            .split_once('x')
+            .ok_or_else(|| format!("Expected WIDTHxHEIGHT, got {:?}", s))?;
+        let parse = |value: &str| match value.parse() {
+            Ok(value) if value > 0 => Ok(value),
+            _ => Err(format!("Invalid resolution {:?}", s)),
+        };
+        Ok(Resolution {
+            width: parse(width)?,
+            height: parse(height)?,
+        })
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
@@ -1,67 +1,190 @@
-struct RayTracingParams {
-    camera_pos: vec4<f32>,
-    aspect_ratio: f32,
-    spheres_count: u32,
-    time_elapsed: f32,
-    _padding: f32,
-};
+#include "layout.wgsl"
+#include "primitives.wgsl"
 
-struct Sphere {
-    pos: vec3<f32>,
-    r: f32,
-    vel: vec3<f32>,
-    _padding: f32,
-    _color: vec4<f32>,
+struct CollisionLog {
+    count: atomic<u32>,
+    events: array<CollisionEvent>,
 }
 
 @group(0) @binding(0) var<uniform> params: RayTracingParams;
 @group(0) @binding(1) var<storage, read> spheres_in: array<Sphere>;
 @group(0) @binding(2) var<storage, read_write> spheres_out: array<Sphere>;
+@group(0) @binding(3) var<storage, read_write> collisions: CollisionLog;
+@group(0) @binding(4) var<storage, read> primitives: PrimitiveList;
+
+// Walls are numbered in the order they're tested: max x, min x, max y, min y, max z, min z,
+// followed by the game's player paddle, opponent paddle and ball, then the primitives
+fn log_collision(index: u32, other: u32, wall: u32, normal: vec3<f32>, speed: f32) {
+    let slot = atomicAdd(&collisions.count, 1u);
+    if slot < arrayLength(&collisions.events) {
+        collisions.events[slot] = CollisionEvent(normal, speed, index, other, wall, params.frame_index);
+    }
+}
+
+// Mirrors the velocity along the contact normal
+fn bounce(index: u32, other: u32, wall: u32, normal: vec3<f32>, speed: f32) {
+    log_collision(index, other, wall, normal, speed);
+    let vel = spheres_out[index].vel.xyz;
+    spheres_out[index].vel = vec4<f32>(vel - normal * dot(vel, normal) * 2.0, spheres_out[index].vel.w);
+}
+
+// Outward normal in xyz and signed distance in w from p to the surface of a box
+// centered on the origin, negative inside
+fn box_distance(p: vec3<f32>, half_size: vec3<f32>) -> vec4<f32> {
+    let side = select(vec3<f32>(-1.0), vec3<f32>(1.0), p >= vec3<f32>(0.0));
+    let q = abs(p) - half_size;
+    if any(q > vec3<f32>(0.0)) {
+        let outside = max(q, vec3<f32>(0.0));
+        return vec4<f32>(side * outside / length(outside), length(outside));
+    }
+    // Inside, the nearest face is the way out
+    if q.x >= q.y && q.x >= q.z {
+        return vec4<f32>(side.x, 0.0, 0.0, q.x);
+    } else if q.y >= q.z {
+        return vec4<f32>(0.0, side.y, 0.0, q.y);
+    }
+    return vec4<f32>(0.0, 0.0, side.z, q.z);
+}
+
+// Same for any primitive, planes are solid behind them and disks have no inside
+fn primitive_distance(primitive: Primitive, p: vec3<f32>) -> vec4<f32> {
+    let local = unrotate(primitive.rotation, p - primitive.pos);
+    switch primitive.kind {
+        case PRIMITIVE_PLANE: {
+            return vec4<f32>(primitive_normal(primitive), local.z);
+        }
+        case PRIMITIVE_DISK: {
+            let radial = length(local.xy);
+            let closest = local.xy * select(1.0, primitive.radius / radial, radial > primitive.radius);
+            let diff = local - vec3<f32>(closest, 0.0);
+            // A center on the disk leaves through the front
+            var normal = vec3<f32>(0.0, 0.0, 1.0);
+            if length(diff) > 0.0 {
+                normal = normalize(diff);
+            }
+            return vec4<f32>(rotate(primitive.rotation, normal), length(diff));
+        }
+        case PRIMITIVE_BOX: {
+            return box_distance(p - primitive.pos, primitive.half_size);
+        }
+        default: {
+            let surface = box_distance(local, primitive.half_size);
+            return vec4<f32>(rotate(primitive.rotation, surface.xyz), surface.w);
+        }
+    }
+}
 
 @compute
 @workgroup_size(1)
 fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
     let index = global_invocation_id.x;
     spheres_out[index] = spheres_in[index];
-    spheres_out[index].pos += spheres_in[index].vel * params.time_elapsed;
+    spheres_out[index].pos += spheres_in[index].vel.xyz * params.time_elapsed;
 
     var collided = false;
     var normal = vec3<f32>(0.0, 0.0, 0.0);
+    var other = NO_INDEX;
+    var wall = NO_INDEX;
+    // Walls don't move
+    var other_vel = vec3<f32>(0.0, 0.0, 0.0);
 
-    if (4.0 - spheres_out[index].pos.x) < spheres_out[index].r {
+    if (ROOM_MAX.x - spheres_out[index].pos.x) < spheres_out[index].r {
         collided = true;
         normal = vec3<f32>(-1.0, 0.0, 0.0);
-    } else if (4.0 + spheres_out[index].pos.x) < spheres_out[index].r {
+        wall = 0u;
+    } else if (spheres_out[index].pos.x - ROOM_MIN.x) < spheres_out[index].r {
         collided = true;
         normal = vec3<f32>(1.0, 0.0, 0.0);
-    } else if (2.0 - spheres_out[index].pos.y) < spheres_out[index].r {
+        wall = 1u;
+    } else if (ROOM_MAX.y - spheres_out[index].pos.y) < spheres_out[index].r {
         collided = true;
         normal = vec3<f32>(0.0, -1.0, 0.0);
-    } else if (2.0 + spheres_out[index].pos.y) < spheres_out[index].r {
+        wall = 2u;
+    } else if (spheres_out[index].pos.y - ROOM_MIN.y) < spheres_out[index].r {
         collided = true;
         normal = vec3<f32>(0.0, 1.0, 0.0);
-    } else if (16.0 - spheres_out[index].pos.z) < spheres_out[index].r {
+        wall = 3u;
+    } else if (ROOM_MAX.z - spheres_out[index].pos.z) < spheres_out[index].r {
         collided = true;
         normal = vec3<f32>(0.0, 0.0, -1.0);
-    } else if (spheres_out[index].pos.z) < spheres_out[index].r {
+        wall = 4u;
+    } else if (spheres_out[index].pos.z - ROOM_MIN.z) < spheres_out[index].r {
         collided = true;
         normal = vec3<f32>(0.0, 0.0, 1.0);
+        wall = 5u;
     } else {
         for (var sphere: u32 = 0u; sphere < params.spheres_count; sphere++) {
             if sphere == index {
                 continue;
             }
-            let sphere_pos = spheres_in[sphere].pos + spheres_in[sphere].vel * params.time_elapsed;
+            let sphere_pos = spheres_in[sphere].pos + spheres_in[sphere].vel.xyz * params.time_elapsed;
             let diff = spheres_out[index].pos - sphere_pos;
             if length(diff) < (spheres_out[index].r + spheres_in[sphere].r) {
                 collided = true;
-                normal = normalize(diff);
+                // Coincident centers have no direction between them, the
+                // higher index goes one way and the lower one the other
+                normal = select(vec3<f32>(select(-1.0, 1.0, index > sphere), 0.0, 0.0), normalize(diff), length(diff) > 0.0);
+                other = sphere;
+                other_vel = spheres_in[sphere].vel.xyz;
                 spheres_out[index].pos = sphere_pos + normal * (spheres_out[index].r + spheres_in[sphere].r);
                 break;
             }
         }
     }
-    if collided {
-        spheres_out[index].vel -= normal * dot(spheres_out[index].vel, normal) * 2.0;
+    let r = spheres_out[index].r;
+    // The game's paddles and ball push the spheres away without being pushed back
+    if !collided && params.player_paddle.w > 0.0 {
+        for (var paddle = 0u; paddle < 2u; paddle++) {
+            let center = select(params.opponent_paddle.xyz, params.player_paddle.xyz, paddle == 0u);
+            let closest = clamp(spheres_out[index].pos, center - PADDLE_HALF_SIZE, center + PADDLE_HALF_SIZE);
+            let diff = spheres_out[index].pos - closest;
+            if length(diff) < r {
+                collided = true;
+                wall = 6u + paddle;
+                if length(diff) > 0.0 {
+                    normal = normalize(diff);
+                    spheres_out[index].pos = closest + normal * r;
+                } else {
+                    // A center inside the paddle leaves through the face towards the middle of the room
+                    normal = vec3<f32>(0.0, 0.0, sign((ROOM_MIN.z + ROOM_MAX.z) * 0.5 - center.z));
+                    spheres_out[index].pos.z = center.z + normal.z * (PADDLE_HALF_SIZE.z + r);
+                }
+                break;
+            }
+        }
+    }
+    if !collided && params.ball.r > 0.0 {
+        let diff = spheres_out[index].pos - params.ball.pos;
+        if length(diff) < r + params.ball.r {
+            collided = true;
+            wall = 8u;
+            normal = select(vec3<f32>(0.0, 1.0, 0.0), normalize(diff), length(diff) > 0.0);
+            other_vel = params.ball.vel.xyz;
+            spheres_out[index].pos = params.ball.pos + normal * (r + params.ball.r);
+        }
+    }
+
+    // How fast the two close in along the normal
+    let speed = dot(other_vel - spheres_out[index].vel.xyz, normal);
+    // Ones that are already moving apart would get turned back into each other
+    if collided && speed > 0.0 {
+        bounce(index, other, wall, normal, speed);
+    }
+
+    // Primitives don't move either. Each one is resolved in turn whatever else
+    // the sphere touched, so it can't sink into a ramp while it rolls along a
+    // wall or into a box another sphere pushed it against.
+    for (var primitive = 0u; primitive < primitives.count; primitive++) {
+        let surface = primitive_distance(primitives.items[primitive], spheres_out[index].pos);
+        if surface.w < r {
+            spheres_out[index].pos += surface.xyz * (r - surface.w);
+            let speed = -dot(spheres_out[index].vel.xyz, surface.xyz);
+            if speed > 0.0 {
+                bounce(index, NO_INDEX, 9u + primitive, surface.xyz, speed);
+            }
+        }
     }
+
+    // Pushed back in, a fast sphere can get past a wall in a single step
+    spheres_out[index].pos = clamp(spheres_out[index].pos, ROOM_MIN + r, ROOM_MAX - r);
 }
@@ -0,0 +1,238 @@
+use std::{
+    convert::Infallible,
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write as _},
+    mem,
+    path::Path,
+};
+
+use crate::{
+    layout::{PhysicsDiagnostics, RayTracingParams},
+    readback::ReadbackRing,
+    Sphere, MAX_SPHERES_COUNT,
+};
+
+// Steps whose results can wait for their readback before new ones get dropped
+const SLOTS: usize = 8;
+
+// Reduces the spheres a physics pass wrote to a few totals that should hold
+// steady while the collision code is right, read back for the steps it runs on
+pub struct Diagnostics {
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::ComputePipeline,
+    bind_groups: [wgpu::BindGroup; 2],
+    result_buffer: wgpu::Buffer,
+    readbacks: ReadbackRing<()>,
+    latest: Option<PhysicsDiagnostics>,
+}
+
+impl Diagnostics {
+    pub fn new(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        uniform_buffer: &wgpu::Buffer,
+        spheres_buffers: &[wgpu::Buffer; 2],
+    ) -> Self {
+        let result_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: mem::size_of::<PhysicsDiagnostics>() as _,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            mem::size_of::<RayTracingParams>() as _
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            (mem::size_of::<Sphere>() * MAX_SPHERES_COUNT) as _,
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            mem::size_of::<PhysicsDiagnostics>() as _,
+                        ),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        // Even frames write the second buffer, odd ones the first
+        let bind_groups = [&spheres_buffers[1], &spheres_buffers[0]].map(|spheres_buffer| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: spheres_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: result_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            module: shader,
+            entry_point: "main",
+        });
+        Diagnostics {
+            pipeline_layout,
+            pipeline,
+            bind_groups,
+            result_buffer,
+            readbacks: ReadbackRing::new(device, SLOTS, mem::size_of::<PhysicsDiagnostics>() as _),
+            latest: None,
+        }
+    }
+
+    // For shader reloading
+    pub fn pipeline_layout(&self) -> &wgpu::PipelineLayout {
+        &self.pipeline_layout
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: wgpu::ComputePipeline) {
+        self.pipeline = pipeline;
+    }
+
+    // Runs after the physics pass, has to be followed by `start_readback`
+    // once the encoder is submitted
+    pub fn reduce(&mut self, encoder: &mut wgpu::CommandEncoder, odd_frame: bool) {
+        {
+            let mut cpass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            cpass.set_pipeline(&self.pipeline);
+            cpass.set_bind_group(0, &self.bind_groups[if odd_frame { 1 } else { 0 }], &[]);
+            cpass.dispatch_workgroups(1, 1, 1);
+        }
+        // A step is skipped when the readbacks fall behind, its tick is missing
+        if let Some(buffer) = self.readbacks.next(()) {
+            encoder.copy_buffer_to_buffer(
+                &self.result_buffer,
+                0,
+                buffer,
+                0,
+                mem::size_of::<PhysicsDiagnostics>() as _,
+            );
+        }
+    }
+
+    pub fn start_readback(&mut self) {
+        self.readbacks.start_readback();
+    }
+
+    // Results of the steps read back since the last call, oldest first
+    pub fn poll(&mut self) -> Vec<PhysicsDiagnostics> {
+        let mut results = Vec::new();
+        let Ok(()) = self.readbacks.poll(|data, ()| {
+            results.push(*bytemuck::from_bytes::<PhysicsDiagnostics>(data));
+            Ok::<_, Infallible>(())
+        });
+        if let Some(&last) = results.last() {
+            self.latest = Some(last);
+        }
+        results
+    }
+
+    pub fn latest(&self) -> Option<PhysicsDiagnostics> {
+        self.latest
+    }
+}
+
+impl fmt::Display for PhysicsDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Kinetic energy {:.6}, momentum ({:.6}, {:.6}, {:.6}), ",
+            self.kinetic_energy, self.momentum[0], self.momentum[1], self.momentum[2]
+        )?;
+        write!(
+            f,
+            "{} overlapping pairs, max penetration {:.6}, {} spheres outside the room",
+            self.overlapping_pairs, self.max_penetration, self.spheres_outside
+        )
+    }
+}
+
+impl PhysicsDiagnostics {
+    // Short enough for the window title
+    pub fn summary(&self) -> String {
+        format!(
+            "energy {:.4}, {} overlapping, {} outside",
+            self.kinetic_energy, self.overlapping_pairs, self.spheres_outside
+        )
+    }
+}
+
+// One CSV row per step
+pub struct DiagnosticsLog {
+    writer: BufWriter<File>,
+}
+
+impl DiagnosticsLog {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(
+            writer,
+            "tick,kinetic_energy,momentum_x,momentum_y,momentum_z,\
+             overlapping_pairs,max_penetration,spheres_outside"
+        )?;
+        Ok(DiagnosticsLog { writer })
+    }
+
+    pub fn write(&mut self, diagnostics: &PhysicsDiagnostics) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{},{},{}",
+            diagnostics.tick,
+            diagnostics.kinetic_energy,
+            diagnostics.momentum[0],
+            diagnostics.momentum[1],
+            diagnostics.momentum[2],
+            diagnostics.overlapping_pairs,
+            diagnostics.max_penetration,
+            diagnostics.spheres_outside
+        )
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,75 @@
+#include "layout.wgsl"
+
+@group(0) @binding(0) var<uniform> params: RayTracingParams;
+@group(0) @binding(1) var<storage, read> spheres: array<Sphere>;
+@group(0) @binding(2) var<storage, read_write> diagnostics: PhysicsDiagnostics;
+
+var<workgroup> momentum: array<vec3<f32>, REDUCTION_SIZE>;
+var<workgroup> kinetic_energy: array<f32, REDUCTION_SIZE>;
+var<workgroup> overlapping_pairs: array<u32, REDUCTION_SIZE>;
+var<workgroup> max_penetration: array<f32, REDUCTION_SIZE>;
+var<workgroup> spheres_outside: array<u32, REDUCTION_SIZE>;
+
+// The physics pass clamps to the walls, rounding can leave a sphere this far in
+const WALL_EPSILON: f32 = 0.0001;
+
+// Solid spheres of unit density
+fn mass(r: f32) -> f32 {
+    return 4.0 / 3.0 * 3.14159265 * r * r * r;
+}
+
+@compute
+@workgroup_size(REDUCTION_SIZE)
+fn main(@builtin(local_invocation_index) thread: u32) {
+    // Every thread sums a strided share of the spheres, and of the pairs they
+    // start
+    var thread_momentum = vec3<f32>(0.0, 0.0, 0.0);
+    var thread_energy = 0.0;
+    var thread_pairs = 0u;
+    var thread_penetration = 0.0;
+    var thread_outside = 0u;
+    for (var index = thread; index < params.spheres_count; index += REDUCTION_SIZE) {
+        let sphere = spheres[index];
+        let vel = sphere.vel.xyz;
+        thread_momentum += mass(sphere.r) * vel;
+        thread_energy += 0.5 * mass(sphere.r) * dot(vel, vel);
+        // Sunk into a wall counts, not only a center past it
+        if any(sphere.pos - sphere.r < ROOM_MIN - WALL_EPSILON) || any(sphere.pos + sphere.r > ROOM_MAX + WALL_EPSILON) {
+            thread_outside += 1u;
+        }
+        for (var other = index + 1u; other < params.spheres_count; other++) {
+            let penetration = sphere.r + spheres[other].r - distance(sphere.pos, spheres[other].pos);
+            if penetration > 0.0 {
+                thread_pairs += 1u;
+                thread_penetration = max(thread_penetration, penetration);
+            }
+        }
+    }
+    momentum[thread] = thread_momentum;
+    kinetic_energy[thread] = thread_energy;
+    overlapping_pairs[thread] = thread_pairs;
+    max_penetration[thread] = thread_penetration;
+    spheres_outside[thread] = thread_outside;
+    workgroupBarrier();
+
+    for (var stride = REDUCTION_SIZE / 2u; stride > 0u; stride /= 2u) {
+        if thread < stride {
+            momentum[thread] += momentum[thread + stride];
+            kinetic_energy[thread] += kinetic_energy[thread + stride];
+            overlapping_pairs[thread] += overlapping_pairs[thread + stride];
+            max_penetration[thread] = max(max_penetration[thread], max_penetration[thread + stride]);
+            spheres_outside[thread] += spheres_outside[thread + stride];
+        }
+        workgroupBarrier();
+    }
+    if thread == 0u {
+        diagnostics = PhysicsDiagnostics(
+            momentum[0],
+            kinetic_energy[0],
+            overlapping_pairs[0],
+            max_penetration[0],
+            spheres_outside[0],
+            params.frame_index,
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use std::{error, fmt, io, path::PathBuf};
+
+// What stops a command, from setting up the device to writing its results
+#[derive(Debug)]
+pub enum Error {
+    Window(winit::error::OsError),
+    Surface(wgpu::CreateSurfaceError),
+    NoAdapter,
+    Device(wgpu::RequestDeviceError),
+    ColorLut(String),
+    Shader(String),
+    Layout(String),
+    Scene(String),
+    State(String),
+    Export(String),
+    Replay(String),
+    // The file the diagnostics were to be written to
+    Diagnostics(PathBuf, io::Error),
+    // The directory the analysis was to be written to
+    Analysis(PathBuf, io::Error),
+    Baseline(String),
+    Bench(String),
+    Report(String),
+    // Worst GPU time change and the allowed one, in percent
+    Regression(f32, f32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Window(err) => write!(f, "Can't create the window: {}", err),
+            Error::Surface(err) => write!(f, "Can't create the surface: {}", err),
+            Error::NoAdapter => write!(f, "Can't find a suitable adapter"),
+            Error::Device(err) => write!(f, "Can't get a device: {}", err),
+            Error::ColorLut(err) => write!(f, "Can't load the color grading LUT: {}", err),
+            Error::Shader(err) => write!(f, "Can't compose the shaders: {}", err),
+            Error::Layout(err) => write!(f, "Shader struct layouts don't match: {}", err),
+            Error::Scene(err) => write!(f, "Can't load the scene: {}", err),
+            Error::State(err) => write!(f, "Can't load the simulation state: {}", err),
+            Error::Export(err) => write!(f, "Can't export: {}", err),
+            Error::Replay(err) => write!(f, "Can't replay: {}", err),
+            Error::Diagnostics(path, err) => write!(
+                f,
+                "Can't start the diagnostics in {}: {}",
+                path.display(),
+                err
+            ),
+            Error::Analysis(directory, err) => write!(
+                f,
+                "Can't start the analysis in {}: {}",
+                directory.display(),
+                err
+            ),
+            Error::Baseline(err) => write!(f, "Can't load the baseline: {}", err),
+            Error::Bench(err) => write!(f, "Can't benchmark: {}", err),
+            Error::Report(err) => write!(f, "Can't write the report: {}", err),
+            Error::Regression(worst, allowed) => write!(
+                f,
+                "GPU time regressed by {:.1}%, more than the allowed {:.1}%",
+                worst, allowed
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Window(err) => Some(err),
+            Error::Surface(err) => Some(err),
+            Error::Device(err) => Some(err),
+            Error::Diagnostics(_, err) => Some(err),
+            Error::Analysis(_, err) => Some(err),
+            Error::NoAdapter
+            | Error::ColorLut(_)
+            | Error::Shader(_)
+            | Error::Layout(_)
+            | Error::Scene(_)
+            | Error::State(_)
+            | Error::Export(_)
+            | Error::Replay(_)
+            | Error::Baseline(_)
+            | Error::Bench(_)
+            | Error::Report(_)
+            | Error::Regression(..) => None,
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use pollster::FutureExt as _;
+use std::{
+    fs,
+    io::Write as _,
+    process::{Child, Command, Stdio},
+};
+use tracing::info;
+
+use crate::{
+    cli::{AdapterArgs, ExportArgs, ExportFormat},
+    error::Error,
+    primitives::Scene,
+    screenshot::Image,
+    state::SimulationState,
+    Renderer, RendererSettings,
+};
+
+// Unless a state brings its own
+const SAMPLES_PER_PIXEL: u32 = 16;
+const MAX_BOUNCES: u32 = 5;
+
+enum Sink {
+    Png,
+    Encoder(Child),
+}
+
+impl Sink {
+    fn new(args: &ExportArgs) -> Result<Self, String> {
+        match args.format {
+            ExportFormat::Png => {
+                fs::create_dir_all(&args.output)
+                    .map_err(|err| format!("Can't create {}: {}", args.output.display(), err))?;
+                Ok(Sink::Png)
+            }
+            ExportFormat::Video => {
+                // yuv420p has a chroma sample for every 2x2 pixels
+                if args.resolution.width % 2 == 1 || args.resolution.height % 2 == 1 {
+                    return Err(format!(
+                        "Videos need an even width and height, {} isn't",
+                        args.resolution
+                    ));
+                }
+                let resolution = args.resolution.to_string();
+                let fps = args.fps.to_string();
+                let encoder = Command::new(&args.ffmpeg)
+                    .args([
+                        "-y",
+                        "-loglevel",
+                        "error",
+                        "-f",
+                        "rawvideo",
+                        "-pix_fmt",
+                        "rgba",
+                    ])
+                    .args(["-s", &resolution, "-r", &fps, "-i", "-"])
+                    // The most widely playable pixel format
+                    .args(["-pix_fmt", "yuv420p"])
+                    .arg(&args.output)
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .map_err(|err| format!("Can't run {}: {}", args.ffmpeg.display(), err))?;
+                Ok(Sink::Encoder(encoder))
+            }
+        }
+    }
+
+    fn write(
+        &mut self,
+        args: &ExportArgs,
+        frame: u32,
+        image: &Image,
+        metadata: &[(String, String)],
+    ) -> Result<(), String> {
+        match self {
+            Sink::Png => {
+                let path = args.output.join(format!("frame-{:05}.png", frame));
+                image.write_png(&path, metadata)
+            }
+            Sink::Encoder(encoder) => {
+                let input = encoder.stdin.as_mut().expect("Can't get the encoder input");
+                let Err(err) = input.write_all(&image.pixels) else {
+                    return Ok(());
+                };
+                // Usually a broken pipe because the encoder gave up, its exit
+                // status says more
+                drop(encoder.stdin.take());
+                match encoder.wait() {
+                    Ok(status) if !status.success() => Err(format!(
+                        "The encoder exited with {} before frame {}",
+                        status, frame
+                    )),
+                    _ => Err(format!(
+                        "Can't pipe frame {} to the encoder: {}",
+                        frame, err
+                    )),
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> Result<(), String> {
+        if let Sink::Encoder(mut encoder) = self {
+            // Closing the input ends the video
+            drop(encoder.stdin.take());
+            let status = encoder
+                .wait()
+                .map_err(|err| format!("Can't wait for the encoder: {}", err))?;
+            if !status.success() {
+                return Err(format!("The encoder failed with {}", status));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn export(renderer: &mut Renderer, args: &ExportArgs, frames: u32) -> Result<(), String> {
+    let mut sink = Sink::new(args)?;
+    for frame in 0..frames {
+        let metadata = renderer.metadata();
+        renderer.capture_frame();
+        renderer
+            .render_and_wait(frame % 2 == 1)
+            .map_err(|err| format!("Can't render frame {}: {}", frame, err))?;
+        let image = renderer
+            .captured_frame
+            .take()
+            .ok_or_else(|| format!("Can't read back frame {}", frame))?;
+        sink.write(args, frame, &image, &metadata)?;
+        if (frame + 1) % args.fps == 0 {
+            info!("Exported {}s of {}s", (frame + 1) / args.fps, args.duration);
+        }
+    }
+    sink.finish()
+}
+
+pub fn run(args: &ExportArgs, adapter: &AdapterArgs) -> Result<(), Error> {
+    // Before the device, a run too short for a single frame has nothing to write
+    let frames = (args.duration * args.fps as f32).round() as u32;
+    if frames == 0 {
+        return Err(Error::Export(format!(
+            "{}s at {} fps is less than a frame",
+            args.duration, args.fps
+        )));
+    }
+    let primitives = match &args.scene {
+        Some(path) => Scene::load(path).map_err(Error::Scene)?.primitives,
+        None => Vec::new(),
+    };
+    let state = args
+        .state
+        .as_deref()
+        .map(SimulationState::load)
+        .transpose()
+        .map_err(Error::State)?;
+    let settings = RendererSettings {
+        spheres_count: args.spheres,
+        seed: Some(args.seed),
+        samples_per_pixel: args.samples.unwrap_or(SAMPLES_PER_PIXEL),
+        max_bounces: args.max_bounces.unwrap_or(MAX_BOUNCES),
+        primitives,
+        color_lut: args.lut.clone(),
+        ..adapter.renderer_settings()
+    };
+    let mut renderer =
+        Renderer::new_headless(args.resolution.width, args.resolution.height, &settings)
+            .block_on()?;
+    // Every frame advances the simulation by the same step, however long it
+    // takes to render
+    renderer.time_step = Some(1.0 / args.fps as f32);
+    renderer.resolution_scaler.enabled = false;
+    renderer.resize_render_targets();
+    if let Some(state) = state {
+        renderer.load_state(state);
+        // Given on the command line, they win over the state's
+        if let Some(samples) = args.samples {
+            renderer.samples_per_pixel = samples;
+        }
+        if let Some(max_bounces) = args.max_bounces {
+            renderer.max_bounces = max_bounces;
+        }
+    }
+    if let Some(path) = &args.diagnostics {
+        renderer
+            .start_diagnostics_log(path)
+            .map_err(|err| Error::Diagnostics(path.clone(), err))?;
+    }
+    if let Some(directory) = &args.analysis {
+        renderer
+            .start_analysis(directory)
+            .map_err(|err| Error::Analysis(directory.clone(), err))?;
+    }
+    let result = export(&mut renderer, args, frames);
+    renderer.finish_logs();
+    result.map_err(Error::Export)?;
+    if let Some(diagnostics) = renderer.physics_diagnostics() {
+        info!("{}", diagnostics);
+    }
+    info!("Exported {} frames to {}", frames, args.output.display());
+    Ok(())
+}
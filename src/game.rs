@@ -0,0 +1,450 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::{
+    layout::Sphere,
+    shaders::{PADDLE_HALF_SIZE, ROOM_MAX, ROOM_MIN},
+    MAX_FRAME_TIME,
+};
+
+// First to this many points wins
+const WINNING_SCORE: u32 = 10;
+// The paddles move in planes in front of the near and the far wall
+pub const PLAYER_PADDLE_Z: f32 = 1.5;
+const OPPONENT_PADDLE_Z: f32 = 15.0;
+const BALL_RADIUS: f32 = 0.25;
+const BALL_COLOR: [f32; 4] = [0.9, 0.9, 0.8, 0.3];
+const SERVE_SPEED: f32 = 6.0;
+// Seconds the ball waits in the middle of the room before it's served
+const SERVE_DELAY: f32 = 1.0;
+// Every return speeds the ball up by this much, up to MAX_BALL_SPEED
+const RETURN_SPEEDUP: f32 = 1.05;
+const MAX_BALL_SPEED: f32 = 15.0;
+// Sideways speed a return picks up per unit the ball is off the paddle's center
+const SPIN: f32 = 3.0;
+// The player's paddle with the arrow keys, the mouse moves it right away
+const PLAYER_SPEED: f32 = 6.0;
+const OPPONENT_SPEED: f32 = 2.5;
+const MAX_STEP: f32 = 1.0 / 240.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameState {
+    Menu,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Side {
+    Player,
+    Opponent,
+}
+
+// ray-ten's game: the player's paddle is in front of the near wall, the
+// opponent's in front of the far one, and the ball scores a point when it gets
+// past one of them to the wall behind. The spheres keep bouncing around and off
+// the paddles and the ball, which don't notice them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Game {
+    state: GameState,
+    // Points of the player and of the opponent
+    score: [u32; 2],
+    ball_pos: [f32; 3],
+    ball_vel: [f32; 3],
+    // Paddle centers in their planes
+    player: [f32; 2],
+    opponent: [f32; 2],
+    // Held arrow keys, -1 to 1 along x and y
+    steering: [f32; 2],
+    // Until the ball is served to whoever lost the last point
+    serve_in: f32,
+    receiver: Side,
+    points_played: u32,
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Game {
+            state: GameState::Menu,
+            score: [0, 0],
+            ball_pos: room_center(),
+            ball_vel: [0.0; 3],
+            player: [0.0; 2],
+            opponent: [0.0; 2],
+            steering: [0.0; 2],
+            serve_in: SERVE_DELAY,
+            receiver: Side::Opponent,
+            points_played: 0,
+        }
+    }
+
+    pub fn state(&self) -> GameState {
+        self.state
+    }
+
+    // Starts a game from the menu or after one is over, pauses and resumes
+    // the one in play
+    pub fn toggle(&mut self) {
+        match self.state {
+            GameState::Menu | GameState::GameOver => {
+                *self = Game {
+                    state: GameState::Playing,
+                    player: self.player,
+                    steering: self.steering,
+                    ..Game::new()
+                }
+            }
+            GameState::Playing => self.state = GameState::Paused,
+            GameState::Paused => self.state = GameState::Playing,
+        }
+    }
+
+    pub fn steer(&mut self, direction: [f32; 2]) {
+        self.steering = direction;
+    }
+
+    // Puts the player's paddle centered on the target, as far as it fits
+    pub fn move_player(&mut self, target: [f32; 2]) {
+        if self.state != GameState::Paused {
+            self.player = clamp_paddle(target);
+        }
+    }
+
+    // Returns who scored, if anyone did
+    pub fn update(&mut self, time_elapsed: f32) -> Option<Side> {
+        if self.state != GameState::Playing {
+            return None;
+        }
+        let time_elapsed = time_elapsed.min(MAX_FRAME_TIME);
+        let steps = (time_elapsed / MAX_STEP).ceil().max(1.0);
+        for _ in 0..steps as u32 {
+            if let Some(scorer) = self.step(time_elapsed / steps) {
+                return Some(scorer);
+            }
+        }
+        None
+    }
+
+    fn step(&mut self, dt: f32) -> Option<Side> {
+        self.player =
+            clamp_paddle([0, 1].map(|i| self.player[i] + self.steering[i] * PLAYER_SPEED * dt));
+        // Follows the ball while it's coming and drifts back to the middle
+        // while it's going away
+        let target = if self.ball_vel[2] > 0.0 {
+            [self.ball_pos[0], self.ball_pos[1]]
+        } else {
+            [0.0, 0.0]
+        };
+        let reach = OPPONENT_SPEED * dt;
+        self.opponent = clamp_paddle(
+            [0, 1].map(|i| self.opponent[i] + (target[i] - self.opponent[i]).clamp(-reach, reach)),
+        );
+        if self.serve_in > 0.0 {
+            self.serve_in -= dt;
+            if self.serve_in <= 0.0 {
+                self.serve();
+            }
+            return None;
+        }
+        let previous_z = self.ball_pos[2];
+        for i in 0..3 {
+            self.ball_pos[i] += self.ball_vel[i] * dt;
+        }
+        for i in 0..2 {
+            let (min, max) = (ROOM_MIN[i] + BALL_RADIUS, ROOM_MAX[i] - BALL_RADIUS);
+            if self.ball_pos[i] < min {
+                self.ball_pos[i] = min;
+                self.ball_vel[i] = self.ball_vel[i].abs();
+            } else if self.ball_pos[i] > max {
+                self.ball_pos[i] = max;
+                self.ball_vel[i] = -self.ball_vel[i].abs();
+            }
+        }
+        // Where the ball touches the face of each paddle
+        let near = PLAYER_PADDLE_Z + PADDLE_HALF_SIZE[2] + BALL_RADIUS;
+        let far = OPPONENT_PADDLE_Z - PADDLE_HALF_SIZE[2] - BALL_RADIUS;
+        if previous_z >= near && self.ball_pos[2] < near && self.covers(self.player) {
+            self.ball_pos[2] = near;
+            self.return_ball(self.player);
+        } else if previous_z <= far && self.ball_pos[2] > far && self.covers(self.opponent) {
+            self.ball_pos[2] = far;
+            self.return_ball(self.opponent);
+        }
+        if self.ball_pos[2] - BALL_RADIUS <= ROOM_MIN[2] {
+            Some(self.score_point(Side::Opponent))
+        } else if self.ball_pos[2] + BALL_RADIUS >= ROOM_MAX[2] {
+            Some(self.score_point(Side::Player))
+        } else {
+            None
+        }
+    }
+
+    // Whether the ball touches the paddle when it reaches its plane
+    fn covers(&self, paddle: [f32; 2]) -> bool {
+        (0..2).all(|i| (self.ball_pos[i] - paddle[i]).abs() <= PADDLE_HALF_SIZE[i] + BALL_RADIUS)
+    }
+
+    // Sends the ball back faster and angled by how far off the paddle's
+    // center it hit
+    fn return_ball(&mut self, paddle: [f32; 2]) {
+        self.ball_vel[2] = -self.ball_vel[2];
+        let forward = self.ball_vel[2].abs();
+        for (i, center) in paddle.into_iter().enumerate() {
+            // Never so steep that the ball stops crossing the room
+            self.ball_vel[i] =
+                (self.ball_vel[i] + (self.ball_pos[i] - center) * SPIN).clamp(-forward, forward);
+        }
+        let speed = length(self.ball_vel);
+        let scale = (speed * RETURN_SPEEDUP).min(MAX_BALL_SPEED) / speed;
+        self.ball_vel = self.ball_vel.map(|v| v * scale);
+    }
+
+    fn score_point(&mut self, scorer: Side) -> Side {
+        self.score[scorer as usize] += 1;
+        self.points_played += 1;
+        if self.score[scorer as usize] >= WINNING_SCORE {
+            self.state = GameState::GameOver;
+        }
+        self.receiver = match scorer {
+            Side::Player => Side::Opponent,
+            Side::Opponent => Side::Player,
+        };
+        self.ball_pos = room_center();
+        self.ball_vel = [0.0; 3];
+        self.serve_in = SERVE_DELAY;
+        scorer
+    }
+
+    fn serve(&mut self) {
+        // Golden angle steps, consecutive serves go in different directions
+        let angle = self.points_played as f32 * 2.4;
+        let z = match self.receiver {
+            Side::Player => -1.0,
+            Side::Opponent => 1.0,
+        };
+        let direction = [angle.cos() * 0.3, angle.sin() * 0.2, z];
+        let length = length(direction);
+        self.ball_vel = direction.map(|d| d / length * SERVE_SPEED);
+    }
+
+    // The ball as the shaders see it, gone once the game is over
+    pub fn ball(&self) -> Sphere {
+        Sphere {
+            pos: self.ball_pos,
+            r: if self.state == GameState::GameOver {
+                0.0
+            } else {
+                BALL_RADIUS
+            },
+            vel: [self.ball_vel[0], self.ball_vel[1], self.ball_vel[2], 0.0],
+            color: BALL_COLOR,
+        }
+    }
+
+    pub fn player_paddle(&self) -> [f32; 4] {
+        [self.player[0], self.player[1], PLAYER_PADDLE_Z, 1.0]
+    }
+
+    pub fn opponent_paddle(&self) -> [f32; 4] {
+        [self.opponent[0], self.opponent[1], OPPONENT_PADDLE_Z, 1.0]
+    }
+}
+
+// The score and what Space does next
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} : {}", self.score[0], self.score[1])?;
+        match self.state {
+            GameState::Menu => write!(f, ", press Space to start"),
+            GameState::Playing => Ok(()),
+            GameState::Paused => write!(f, ", paused, press Space to resume"),
+            GameState::GameOver => write!(
+                f,
+                ", {} won, press Space to play again",
+                if self.score[0] > self.score[1] {
+                    "you"
+                } else {
+                    "the opponent"
+                }
+            ),
+        }
+    }
+}
+
+fn room_center() -> [f32; 3] {
+    [0, 1, 2].map(|i| (ROOM_MIN[i] + ROOM_MAX[i]) / 2.0)
+}
+
+fn clamp_paddle(center: [f32; 2]) -> [f32; 2] {
+    [0, 1].map(|i| {
+        center[i].clamp(
+            ROOM_MIN[i] + PADDLE_HALF_SIZE[i],
+            ROOM_MAX[i] - PADDLE_HALF_SIZE[i],
+        )
+    })
+}
+
+fn length(v: [f32; 3]) -> f32 {
+    v.iter().map(|v| v * v).sum::<f32>().sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: f32 = 1.0 / 240.0;
+    // Where the ball touches the player's paddle
+    const NEAR: f32 = PLAYER_PADDLE_Z + PADDLE_HALF_SIZE[2] + BALL_RADIUS;
+
+    // A game in play with the ball already served
+    fn playing() -> Game {
+        let mut game = Game::new();
+        game.toggle();
+        Game {
+            serve_in: 0.0,
+            ..game
+        }
+    }
+
+    fn speed(vel: [f32; 4]) -> f32 {
+        vel[..3].iter().map(|v| v * v).sum::<f32>().sqrt()
+    }
+
+    fn wait_for_serve(game: &mut Game) {
+        for _ in 0..(SERVE_DELAY / 0.1) as u32 + 1 {
+            assert_eq!(game.update(0.1), None);
+        }
+    }
+
+    #[test]
+    fn space_starts_pauses_resumes_and_restarts() {
+        let mut game = Game::new();
+        assert_eq!(game.state(), GameState::Menu);
+        game.toggle();
+        assert_eq!(game.state(), GameState::Playing);
+        game.toggle();
+        assert_eq!(game.state(), GameState::Paused);
+        game.toggle();
+        assert_eq!(game.state(), GameState::Playing);
+
+        let mut game = Game {
+            state: GameState::GameOver,
+            score: [10, 3],
+            ..playing()
+        };
+        game.toggle();
+        assert_eq!(game.state(), GameState::Playing);
+        assert_eq!(game.score, [0, 0]);
+    }
+
+    #[test]
+    fn only_a_game_in_play_moves() {
+        for state in [GameState::Menu, GameState::Paused, GameState::GameOver] {
+            let mut game = Game {
+                state,
+                ball_vel: [1.0, 1.0, 5.0],
+                ..playing()
+            };
+            let ball = game.ball();
+            assert_eq!(game.update(0.1), None);
+            assert_eq!(game.ball().pos, ball.pos, "{:?}", state);
+        }
+    }
+
+    #[test]
+    fn the_ball_is_served_to_whoever_lost_the_last_point() {
+        let mut game = Game::new();
+        game.toggle();
+        wait_for_serve(&mut game);
+        assert!(game.ball().vel[2] > 0.0);
+
+        let mut game = Game {
+            serve_in: SERVE_DELAY,
+            receiver: Side::Player,
+            ..playing()
+        };
+        wait_for_serve(&mut game);
+        assert!(game.ball().vel[2] < 0.0);
+    }
+
+    #[test]
+    fn getting_past_a_paddle_scores_for_the_other_side() {
+        // The player's paddle is out of the way in the corner
+        let mut game = Game {
+            player: [-3.0, -1.0],
+            ball_pos: [3.0, 1.0, NEAR + 0.1],
+            ball_vel: [0.0, 0.0, -6.0],
+            ..playing()
+        };
+        let scorer = (0..100).find_map(|_| game.update(0.1));
+        assert_eq!(scorer, Some(Side::Opponent));
+        assert_eq!(game.score, [0, 1]);
+        assert_eq!(game.receiver, Side::Player);
+        assert_eq!(game.ball().vel, [0.0; 4]);
+        assert_eq!(game.to_string(), "0 : 1");
+    }
+
+    #[test]
+    fn the_tenth_point_ends_the_game() {
+        let mut game = Game {
+            score: [WINNING_SCORE - 1, 4],
+            opponent: [-3.0, -1.0],
+            ball_pos: [3.0, 1.0, ROOM_MAX[2] - 1.0],
+            ball_vel: [0.0, 0.0, 6.0],
+            ..playing()
+        };
+        let scorer = (0..100).find_map(|_| game.update(0.1));
+        assert_eq!(scorer, Some(Side::Player));
+        assert_eq!(game.state(), GameState::GameOver);
+        assert_eq!(game.ball().r, 0.0);
+        assert_eq!(
+            game.to_string(),
+            "10 : 4, you won, press Space to play again"
+        );
+    }
+
+    #[test]
+    fn returns_pick_up_spin_and_speed() {
+        let mut game = Game {
+            ball_pos: [0.5, 0.0, NEAR + 0.01],
+            ball_vel: [0.0, 0.0, -6.0],
+            ..playing()
+        };
+        assert_eq!(game.update(STEP), None);
+        let vel = game.ball().vel;
+        assert!(vel[2] > 0.0);
+        // Off the center by 0.5 along x, then sped up along with the spin
+        let spin = 0.5 * SPIN;
+        assert!((vel[0] / vel[2] - spin / 6.0).abs() < 1e-4);
+        assert_eq!(vel[1], 0.0);
+        let expected = (6.0f32 * 6.0 + spin * spin).sqrt() * RETURN_SPEEDUP;
+        assert!((speed(vel) - expected).abs() < 1e-4);
+    }
+
+    #[test]
+    fn returns_stay_under_the_speed_limit() {
+        let mut game = Game {
+            ball_pos: [0.0, 0.0, NEAR + 0.01],
+            ball_vel: [0.0, 0.0, -MAX_BALL_SPEED],
+            ..playing()
+        };
+        assert_eq!(game.update(STEP), None);
+        assert!(game.ball().vel[2] > 0.0);
+        assert!((speed(game.ball().vel) - MAX_BALL_SPEED).abs() < 1e-3);
+    }
+
+    #[test]
+    fn a_fast_ball_cannot_tunnel_through_the_paddle() {
+        // Crosses the paddle and most of the room behind it in a single step
+        let mut game = Game {
+            ball_pos: [0.0, 0.0, NEAR + 0.01],
+            ball_vel: [0.0, 0.0, -300.0],
+            ..playing()
+        };
+        assert_eq!(game.update(STEP), None);
+        assert!(game.ball().vel[2] > 0.0);
+        assert!(game.ball().pos[2] >= NEAR);
+        assert_eq!(game.score, [0, 0]);
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+use tracing::warn;
+
+// Checking every frame would mostly measure the file system
+const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderFile {
+    Compute,
+    Trace,
+    Diagnostics,
+}
+
+impl ShaderFile {
+    const ALL: [ShaderFile; 3] = [
+        ShaderFile::Compute,
+        ShaderFile::Trace,
+        ShaderFile::Diagnostics,
+    ];
+
+    pub fn path(self) -> PathBuf {
+        source_path(match self {
+            ShaderFile::Compute => "compute.wgsl",
+            ShaderFile::Trace => "shader.wgsl",
+            ShaderFile::Diagnostics => "diagnostics.wgsl",
+        })
+    }
+}
+
+// Shared sources the shaders `#include`, with the shaders that include them
+const INCLUDES: [(&str, &[ShaderFile]); 1] =
+    [("primitives.wgsl", &[ShaderFile::Compute, ShaderFile::Trace])];
+
+// The sources next to the crate manifest, i.e. the ones that get embedded
+fn source_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src").join(name)
+}
+
+impl fmt::Display for ShaderFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path().display())
+    }
+}
+
+// Polls the modification times of the shader sources and their includes
+pub struct ShaderWatcher {
+    modified: [Option<SystemTime>; 3],
+    includes_modified: [Option<SystemTime>; 1],
+    // Includes saved since the start, in place of the embedded ones
+    includes: Vec<(&'static str, String)>,
+    last_check: Instant,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> Self {
+        ShaderWatcher {
+            modified: ShaderFile::ALL.map(|file| modified(&file.path())),
+            includes_modified: INCLUDES.map(|(name, _)| modified(&source_path(name))),
+            includes: Vec::new(),
+            last_check: Instant::now(),
+        }
+    }
+
+    // Sources of the shaders saved since the last call, or that include a
+    // source that was, to be composed with `includes`
+    pub fn changed(&mut self) -> Vec<(ShaderFile, String)> {
+        if self.last_check.elapsed() < CHECK_INTERVAL {
+            return Vec::new();
+        }
+        self.last_check = Instant::now();
+        let mut files = Vec::new();
+        for (file, last_modified) in ShaderFile::ALL.into_iter().zip(&mut self.modified) {
+            let modified = modified(&file.path());
+            if modified != *last_modified {
+                *last_modified = modified;
+                files.push(file);
+            }
+        }
+        for ((name, includers), last_modified) in
+            INCLUDES.into_iter().zip(&mut self.includes_modified)
+        {
+            let path = source_path(name);
+            let modified = modified(&path);
+            if modified == *last_modified {
+                continue;
+            }
+            *last_modified = modified;
+            match fs::read_to_string(&path) {
+                Ok(source) => {
+                    self.includes.retain(|(included, _)| *included != name);
+                    self.includes.push((name, source));
+                    files.extend(includers);
+                }
+                Err(err) => warn!("Can't read {}: {}", path.display(), err),
+            }
+        }
+        let mut changed = Vec::new();
+        for file in ShaderFile::ALL {
+            if !files.contains(&file) {
+                continue;
+            }
+            match fs::read_to_string(file.path()) {
+                Ok(source) => changed.push((file, source)),
+                Err(err) => warn!("Can't read {}: {}", file, err),
+            }
+        }
+        changed
+    }
+
+    // Names and sources of the includes that were saved, for `shaders::compose_with`
+    pub fn includes(&self) -> Vec<(&str, &str)> {
+        self.includes
+            .iter()
+            .map(|(name, source)| (*name, source.as_str()))
+            .collect()
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -0,0 +1,223 @@
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Write as _, mem};
+
+// How the field types of GPU structs are spelled in WGSL
+pub trait WgslType {
+    const WGSL: &'static str;
+}
+
+impl WgslType for f32 {
+    const WGSL: &'static str = "f32";
+}
+
+impl WgslType for u32 {
+    const WGSL: &'static str = "u32";
+}
+
+impl WgslType for [f32; 2] {
+    const WGSL: &'static str = "vec2<f32>";
+}
+
+impl WgslType for [f32; 3] {
+    const WGSL: &'static str = "vec3<f32>";
+}
+
+impl WgslType for [f32; 4] {
+    const WGSL: &'static str = "vec4<f32>";
+}
+
+// Structs nest in others declared after them
+impl WgslType for Sphere {
+    const WGSL: &'static str = "Sphere";
+}
+
+pub struct Field {
+    pub name: &'static str,
+    pub wgsl_type: &'static str,
+    pub offset: usize,
+    pub size: usize,
+}
+
+pub trait GpuStruct {
+    const NAME: &'static str;
+    fn size() -> usize;
+    fn fields() -> Vec<Field>;
+}
+
+// Declares the structs shared with the shaders, the WGSL side is generated
+// from the same list so the two can't drift apart
+macro_rules! gpu_structs {
+    ($(struct $name:ident { $($field:ident: $ty:ty,)* })*) => {
+        $(
+            #[repr(C)]
+            #[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+            pub struct $name {
+                $(pub $field: $ty,)*
+            }
+
+            impl GpuStruct for $name {
+                const NAME: &'static str = stringify!($name);
+
+                fn size() -> usize {
+                    mem::size_of::<$name>()
+                }
+
+                fn fields() -> Vec<Field> {
+                    vec![$(Field {
+                        name: stringify!($field),
+                        wgsl_type: <$ty as WgslType>::WGSL,
+                        offset: mem::offset_of!($name, $field),
+                        size: mem::size_of::<$ty>(),
+                    },)*]
+                }
+            }
+        )*
+
+        // WGSL declarations of all the shared structs, included as layout.wgsl
+        pub fn preamble() -> String {
+            let mut preamble = String::new();
+            $(write_struct::<$name>(&mut preamble);)*
+            preamble
+        }
+
+        // Compiles the shader with naga and compares its struct layouts to the
+        // Rust ones, alignment rules differ so matching fields aren't enough
+        pub fn check(source: &str) -> Result<(), String> {
+            let module = naga::front::wgsl::parse_str(source)
+                .map_err(|err| err.emit_to_string(source))?;
+            let mut layouter = naga::proc::Layouter::default();
+            layouter
+                .update(&module.types, &module.constants)
+                .map_err(|err| err.to_string())?;
+            $(check_struct::<$name>(&module, &layouter)?;)*
+            Ok(())
+        }
+    };
+}
+
+// The sphere buffers are allocated for this many
+pub const MAX_SPHERES_COUNT: usize = 1000;
+
+gpu_structs! {
+    struct Sphere {
+        pos: [f32; 3],
+        r: f32,
+        vel: [f32; 4],
+        color: [f32; 4],
+    }
+
+    struct RayTracingParams {
+        camera_pos: [f32; 4],
+        // The game's ball, hidden when the radius is zero
+        ball: Sphere,
+        // Center of each paddle, w is 1 while there's a game and 0 otherwise
+        player_paddle: [f32; 4],
+        opponent_paddle: [f32; 4],
+        aspect_ratio: f32,
+        spheres_count: u32,
+        time_elapsed: f32,
+        shutter_time: f32,
+        samples_per_pixel: u32,
+        aperture: f32,
+        focus_distance: f32,
+        frame_index: u32,
+        focus_point: [f32; 2],
+        debug_view: u32,
+        max_bounces: u32,
+    }
+
+    // A plane, disk or box of a scene, built from the descriptions in primitives.rs
+    struct Primitive {
+        // Center, or any point of a plane
+        pos: [f32; 3],
+        kind: u32,
+        // Boxes
+        half_size: [f32; 3],
+        // Disks
+        radius: f32,
+        // Unit quaternion, planes and disks face along the rotated z axis and
+        // axis-aligned boxes ignore it
+        rotation: [f32; 4],
+        // Reflectivity in w
+        color: [f32; 4],
+    }
+
+    struct CollisionEvent {
+        normal: [f32; 3],
+        speed: f32,
+        sphere: u32,
+        other: u32,
+        wall: u32,
+        tick: u32,
+    }
+
+    struct PhysicsDiagnostics {
+        momentum: [f32; 3],
+        kinetic_energy: f32,
+        overlapping_pairs: u32,
+        max_penetration: f32,
+        spheres_outside: u32,
+        tick: u32,
+    }
+}
+
+fn write_struct<T: GpuStruct>(preamble: &mut String) {
+    writeln!(preamble, "struct {} {{", T::NAME).expect("Can't write the preamble");
+    for field in T::fields() {
+        writeln!(preamble, "    {}: {},", field.name, field.wgsl_type)
+            .expect("Can't write the preamble");
+    }
+    writeln!(preamble, "}}").expect("Can't write the preamble");
+}
+
+fn check_struct<T: GpuStruct>(
+    module: &naga::Module,
+    layouter: &naga::proc::Layouter,
+) -> Result<(), String> {
+    let Some((_, ty)) = module
+        .types
+        .iter()
+        .find(|(_, ty)| ty.name.as_deref() == Some(T::NAME))
+    else {
+        return Err(format!("{} isn't declared in the shader", T::NAME));
+    };
+    let naga::TypeInner::Struct { members, span } = &ty.inner else {
+        return Err(format!("{} isn't a struct in the shader", T::NAME));
+    };
+    if *span as usize != T::size() {
+        return Err(format!(
+            "{} is {} bytes in WGSL and {} bytes in Rust",
+            T::NAME,
+            span,
+            T::size()
+        ));
+    }
+    let fields = T::fields();
+    if members.len() != fields.len() {
+        return Err(format!(
+            "{} has {} fields in WGSL and {} in Rust",
+            T::NAME,
+            members.len(),
+            fields.len()
+        ));
+    }
+    for (member, field) in members.iter().zip(&fields) {
+        let name = member.name.as_deref().unwrap_or_default();
+        let size = layouter[member.ty].size as usize;
+        if name != field.name || member.offset as usize != field.offset || size != field.size {
+            return Err(format!(
+                "{}.{} is {} bytes at offset {} in WGSL, {}.{} is {} bytes at offset {} in Rust",
+                T::NAME,
+                name,
+                size,
+                member.offset,
+                T::NAME,
+                field.name,
+                field.size,
+                field.offset
+            ));
+        }
+    }
+    Ok(())
+}
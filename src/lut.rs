@@ -0,0 +1,191 @@
+use half::f16;
+use std::{fs, path::Path};
+
+// Color grading lookup table in the Adobe/Resolve .cube format, display
+// referred like the grades exported from editing tools
+pub struct ColorLut {
+    size: u32,
+    // As in the file, outputs aren't limited to 0..1
+    texels: Vec<[f32; 3]>,
+    // The input colors the table spans
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+}
+
+impl ColorLut {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = fs::read_to_string(path)
+            .map_err(|err| format!("Can't read {}: {}", path.display(), err))?;
+        Self::parse_cube(&source).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn parse_cube(source: &str) -> Result<Self, String> {
+        let mut size = None;
+        let mut domain_min = [0.0f32; 3];
+        let mut domain_max = [1.0f32; 3];
+        // Where the domain was last set, checked once both ends are known
+        let mut domain_line = 0;
+        let mut texels = Vec::new();
+        for (line_no, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("TITLE") {
+                continue;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let parse_triple = |values: &[&str]| -> Result<[f32; 3], String> {
+                if values.len() != 3 {
+                    return Err(format!("Line {}: expected 3 values", line_no + 1));
+                }
+                let mut triple = [0.0f32; 3];
+                for (value, token) in triple.iter_mut().zip(values) {
+                    *value = token
+                        .parse()
+                        .map_err(|_| format!("Line {}: bad number {:?}", line_no + 1, token))?;
+                }
+                Ok(triple)
+            };
+            match tokens[0] {
+                "LUT_3D_SIZE" => {
+                    size = Some(
+                        tokens
+                            .get(1)
+                            .and_then(|token| token.parse::<u32>().ok())
+                            .filter(|size| (2..=256).contains(size))
+                            .ok_or(format!("Line {}: bad LUT_3D_SIZE", line_no + 1))?,
+                    );
+                }
+                "DOMAIN_MIN" => {
+                    domain_min = parse_triple(&tokens[1..])?;
+                    domain_line = line_no + 1;
+                }
+                "DOMAIN_MAX" => {
+                    domain_max = parse_triple(&tokens[1..])?;
+                    domain_line = line_no + 1;
+                }
+                // The same range on every axis
+                "LUT_3D_INPUT_RANGE" => {
+                    let [min, max] = tokens[1..] else {
+                        return Err(format!("Line {}: expected 2 values", line_no + 1));
+                    };
+                    let parse = |token: &str| {
+                        token
+                            .parse::<f32>()
+                            .map_err(|_| format!("Line {}: bad number {:?}", line_no + 1, token))
+                    };
+                    domain_min = [parse(min)?; 3];
+                    domain_max = [parse(max)?; 3];
+                    domain_line = line_no + 1;
+                }
+                "LUT_1D_SIZE" => return Err("1D LUTs aren't supported".to_owned()),
+                _ if (0..3).any(|i| domain_max[i] <= domain_min[i]) => {
+                    return Err(format!(
+                        "Line {}: the domain is empty, {:?} to {:?}",
+                        domain_line, domain_min, domain_max
+                    ));
+                }
+                _ => texels.push(parse_triple(&tokens)?),
+            }
+        }
+        let size = size.ok_or("Missing LUT_3D_SIZE")?;
+        if texels.len() != (size * size * size) as usize {
+            return Err(format!(
+                "Expected {} entries, got {}",
+                size * size * size,
+                texels.len()
+            ));
+        }
+        Ok(ColorLut {
+            size,
+            texels,
+            domain_min,
+            domain_max,
+        })
+    }
+
+    // The input range as min and max, the grade maps it onto the table
+    pub fn domain(&self) -> ([f32; 3], [f32; 3]) {
+        (self.domain_min, self.domain_max)
+    }
+
+    pub fn create_view(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::TextureView {
+        let extent = wgpu::Extent3d {
+            width: self.size,
+            height: self.size,
+            depth_or_array_layers: self.size,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            // Filterable without extra features and fine for outputs past 1
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let texels: Vec<[f16; 4]> = self
+            .texels
+            .iter()
+            .map(|&[r, g, b]| [r, g, b, 1.0].map(f16::from_f32))
+            .collect();
+        // .cube stores red fastest, then green, then blue, which is exactly x, y, z
+        queue.write_texture(
+            texture.as_image_copy(),
+            bytemuck::cast_slice(&texels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(self.size * 8),
+                rows_per_image: std::num::NonZeroU32::new(self.size),
+            },
+            extent,
+        );
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_keep_their_values_in_a_non_unit_domain() {
+        let source = "\
+TITLE \"log input\"
+LUT_3D_SIZE 2
+LUT_3D_INPUT_RANGE -0.5 4.0
+0.0 0.0 0.0
+1.5 0.0 0.0
+0.0 0.25 0.0
+1.5 0.25 0.0
+0.0 0.0 -0.1
+1.5 0.0 -0.1
+0.0 0.25 -0.1
+1.5 0.25 -0.1
+";
+        let lut = ColorLut::parse_cube(source).unwrap();
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.domain(), ([-0.5; 3], [4.0; 3]));
+        assert_eq!(lut.texels[1], [1.5, 0.0, 0.0]);
+        assert_eq!(lut.texels[7], [1.5, 0.25, -0.1]);
+    }
+
+    #[test]
+    fn domains_are_set_per_axis() {
+        let entries = "0 0 0\n".repeat(8);
+        let source = format!(
+            "LUT_3D_SIZE 2\nDOMAIN_MIN 0.0 -1.0 0.5\nDOMAIN_MAX 2.0 1.0 0.75\n{}",
+            entries
+        );
+        let lut = ColorLut::parse_cube(&source).unwrap();
+        assert_eq!(lut.domain(), ([0.0, -1.0, 0.5], [2.0, 1.0, 0.75]));
+    }
+
+    #[test]
+    fn empty_domains_name_their_line() {
+        let entries = "0 0 0\n".repeat(8);
+        let source = format!("LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 1.0 1.0\n{}", entries);
+        let err = ColorLut::parse_cube(&source).err().unwrap();
+        assert!(err.starts_with("Line 2:"), "{}", err);
+    }
+}
@@ -1,57 +1,228 @@
-use bytemuck::{Pod, Zeroable};
+mod analysis;
+mod autofocus;
+mod bench;
+mod cli;
+mod diagnostics;
+mod error;
+mod export;
+mod game;
+mod hot_reload;
+mod layout;
+mod lut;
+mod postprocess;
+mod primitives;
+mod profiler;
+mod readback;
+mod recovery;
+mod replay;
+mod resolution;
+mod screenshot;
+mod shaders;
+mod snapshot;
+mod state;
+mod tonemap;
+mod upscale;
+
+use analysis::{AnalysisLog, Dropped};
+use autofocus::AutoFocus;
+use bytemuck::Zeroable as _;
+use clap::Parser;
+use cli::{Cli, Command};
+use diagnostics::{Diagnostics, DiagnosticsLog};
+use error::Error;
+use game::{Game, GameState, PLAYER_PADDLE_Z};
+use hot_reload::{ShaderFile, ShaderWatcher};
+use layout::{PhysicsDiagnostics, RayTracingParams, Sphere, MAX_SPHERES_COUNT};
+use lut::ColorLut;
 use pollster::FutureExt as _;
-use rand::{distributions::Uniform, thread_rng, Rng};
-use std::{mem, time::Instant};
-use tracing::debug;
+use postprocess::{PostEffect, PostEffects, PostProcessor};
+use primitives::{Scene, ScenePrimitive, PRIMITIVES_BUFFER_SIZE};
+use profiler::{ProfiledPass, Profiler};
+use rand::{distributions::Uniform, rngs::StdRng, thread_rng, Rng, SeedableRng};
+use recovery::DeviceLoss;
+use replay::{Input, Recorder, ReplaySettings};
+use resolution::{FrameTimer, ResolutionScaler};
+use screenshot::{Image, ScreenCapture};
+use shaders::TILE_SIZE;
+use snapshot::SphereSnapshot;
+use state::{SimulationState, STATE_VERSION};
+use std::{
+    mem,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use tonemap::{ToneMapper, HDR_FORMAT};
+use tracing::{debug, error, info, warn};
+use upscale::Upscaler;
 use winit::{
-    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
+    window::{Fullscreen, Window, WindowBuilder},
 };
 
-const MAX_SPHERES_COUNT: usize = 1000;
+const MAX_SAMPLES_PER_PIXEL: u32 = 64;
+const MAX_BOUNCES: u32 = 16;
 
-#[repr(C)]
-#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
-struct RayTracingParams {
-    camera_pos: [f32; 4],
-    aspect_ratio: f32,
-    spheres_count: u32,
-    time_elapsed: f32,
-    _padding: f32,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DebugView {
+    Off = 0,
+    Normals = 1,
+    Depth = 2,
+    Bounces = 3,
+    SphereIndex = 4,
+    SphereTests = 5,
+    Velocity = 6,
+}
+
+impl DebugView {
+    fn next(self) -> Self {
+        match self {
+            DebugView::Off => DebugView::Normals,
+            DebugView::Normals => DebugView::Depth,
+            DebugView::Depth => DebugView::Bounces,
+            DebugView::Bounces => DebugView::SphereIndex,
+            DebugView::SphereIndex => DebugView::SphereTests,
+            DebugView::SphereTests => DebugView::Velocity,
+            DebugView::Velocity => DebugView::Off,
+        }
+    }
 }
 
-#[repr(C)]
-#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
-struct Sphere {
-    pos: [f32; 3],
-    r: f32,
-    vel: [f32; 4],
-    color: [f32; 4],
+const SAMPLES_PER_PIXEL: [u32; 3] = [1, 4, 16];
+const MIN_FOCUS_DISTANCE: f32 = 0.1;
+// Far enough back to see the whole plane the player's paddle moves in
+const GAME_CAMERA_Z: f32 = -6.0;
+// A stall, e.g. a dragged window, would otherwise move the spheres through
+// each other or play out a game's rally in a single step
+const MAX_FRAME_TIME: f32 = 0.1;
+// Defaults of the dynamic resolution
+const TARGET_FPS: f32 = 60.0;
+const MIN_RENDER_SCALE: f32 = 0.5;
+const MAX_RENDER_SCALE: f32 = 1.0;
+// Steps between the physics diagnostics while they aren't logged
+const DIAGNOSTICS_INTERVAL: u32 = 60;
+// Headless rendering has nothing to pick a format from
+const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+#[derive(Clone)]
+struct RendererSettings {
+    spheres_count: u32,
+    // Random on every run when not set
+    seed: Option<u64>,
+    // Restored simulation state, generated from the seed when not set
+    spheres: Option<Vec<Sphere>>,
+    primitives: Vec<ScenePrimitive>,
+    // The embedded grade.cube when not set
+    color_lut: Option<PathBuf>,
+    samples_per_pixel: u32,
+    max_bounces: u32,
+    present_mode: wgpu::PresentMode,
+    target_fps: f32,
+    min_render_scale: f32,
+    max_render_scale: f32,
+    backends: wgpu::Backends,
+    force_fallback_adapter: bool,
 }
 
-const SAMPLE_COUNT: u32 = 4;
+impl Default for RendererSettings {
+    fn default() -> Self {
+        RendererSettings {
+            spheres_count: MAX_SPHERES_COUNT as u32,
+            seed: None,
+            spheres: None,
+            primitives: Vec::new(),
+            color_lut: None,
+            samples_per_pixel: 4,
+            max_bounces: 5,
+            present_mode: wgpu::PresentMode::Fifo,
+            target_fps: TARGET_FPS,
+            min_render_scale: MIN_RENDER_SCALE,
+            max_render_scale: MAX_RENDER_SCALE,
+            backends: wgpu::Backends::all(),
+            force_fallback_adapter: false,
+        }
+    }
+}
 
 struct Renderer {
-    surface: wgpu::Surface,
+    // Either a window surface or an offscreen texture to render into
+    surface: Option<wgpu::Surface>,
+    offscreen: Option<wgpu::Texture>,
+    // Swapchain textures can't be copied from, so screenshots of the window
+    // are tone mapped once more into this. Made on the first one and sized to
+    // the surface.
+    capture_target: Option<wgpu::Texture>,
     surface_config: wgpu::SurfaceConfiguration,
+    adapter_info: wgpu::AdapterInfo,
     queue: wgpu::Queue,
     device: wgpu::Device,
-    render_pipeline: wgpu::RenderPipeline,
+    device_loss: DeviceLoss,
+    // Kept to request an equivalent device when the current one is lost
+    settings: RendererSettings,
+    trace_pipeline_layout: wgpu::PipelineLayout,
+    trace_pipeline: wgpu::ComputePipeline,
+    compute_pipeline_layout: wgpu::PipelineLayout,
     compute_pipeline: wgpu::ComputePipeline,
     uniform_buffer: wgpu::Buffer,
-    render_bind_groups: [wgpu::BindGroup; 2],
+    spheres_buffers: [wgpu::Buffer; 2],
+    // Collisions of the last physics pass, behind an atomic counter
+    collision_buffer: wgpu::Buffer,
+    primitives_buffer: wgpu::Buffer,
+    diagnostics: Diagnostics,
+    diagnostics_log: Option<DiagnosticsLog>,
+    // The reduction goes over every pair of spheres, so outside the log it
+    // only runs this often, never when None
+    diagnostics_interval: Option<u32>,
+    snapshot: SphereSnapshot,
+    // Waits for the spheres to be read back before it's written, the state is
+    // None until the frame they're copied on
+    state_save: Option<(PathBuf, Option<SimulationState>)>,
+    recorder: Option<Recorder>,
+    analysis: Option<AnalysisLog>,
+    game: Option<Game>,
+    trace_bind_groups: [wgpu::BindGroup; 2],
     compute_bind_groups: [wgpu::BindGroup; 2],
-    multisampled_framebuffer: wgpu::TextureView,
+    output_bind_group_layout: wgpu::BindGroupLayout,
+    output_bind_group: wgpu::BindGroup,
+    hdr_framebuffer: wgpu::TextureView,
+    post_processor: PostProcessor,
+    post_effects: PostEffects,
+    upscaler: Upscaler,
+    tone_mapper: ToneMapper,
+    autofocus: AutoFocus,
+    screen_capture: ScreenCapture,
+    // Where the captured frame goes, kept in `captured_frame` when not set
+    screenshot: Option<(PathBuf, Vec<(String, String)>)>,
+    captured_frame: Option<Image>,
+    resolution_scaler: ResolutionScaler,
+    frame_timer: FrameTimer,
+    profiler: Profiler,
+    gpu_frame_time: Option<f32>,
     camera_x: f32,
+    motion_blur: bool,
+    shutter_angle: f32,
+    aperture: f32,
+    focus_distance: f32,
+    focus_point: [f32; 2],
+    focus_requested: bool,
+    frame_index: u32,
+    debug_view: DebugView,
+    samples_per_pixel: u32,
+    max_bounces: u32,
     aspect_ratio: f32,
     spheres_count: u32,
+    // Fixed simulation step instead of the wall clock time, for reproducible runs
+    time_step: Option<f32>,
+    // Zero-sized surfaces can't be configured, rendering waits for a real size
+    minimized: bool,
+    started: Instant,
     frame_start: Instant,
 }
 
-fn init_spheres() -> Vec<Sphere> {
-    let mut spheres = Vec::with_capacity(MAX_SPHERES_COUNT);
-    let mut rng = thread_rng();
+fn init_spheres(settings: &RendererSettings) -> Vec<Sphere> {
+    let mut spheres = Vec::with_capacity(settings.spheres_count as usize);
+    let mut rng = StdRng::seed_from_u64(settings.seed.unwrap_or_else(|| thread_rng().gen()));
     let x_dist = Uniform::new(-3.0, 3.0);
     let y_dist = Uniform::new(-1.0, 1.0);
     let z_dist = Uniform::new(1.0, 15.0);
@@ -59,7 +230,7 @@ fn init_spheres() -> Vec<Sphere> {
     let vel_dist = Uniform::new(0.0, 0.5);
     let rgb_dist = Uniform::new(0.0, 0.5);
     let refl_dist = Uniform::new(0.0, 0.9);
-    for _ in 0..MAX_SPHERES_COUNT {
+    for _ in 0..settings.spheres_count {
         spheres.push(Sphere {
             pos: [rng.sample(x_dist), rng.sample(y_dist), rng.sample(z_dist)],
             r: rng.sample(r_dist),
@@ -77,33 +248,150 @@ fn init_spheres() -> Vec<Sphere> {
             ],
         })
     }
-    return spheres;
+    spheres
+}
+
+fn create_hdr_framebuffer(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn create_instance(backends: wgpu::Backends) -> wgpu::Instance {
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    })
+}
+
+fn list_adapters(backends: wgpu::Backends) {
+    for adapter in create_instance(backends).enumerate_adapters(backends) {
+        let info = adapter.get_info();
+        println!(
+            "{} ({:?}, {:?}, driver {} {})",
+            info.name, info.backend, info.device_type, info.driver, info.driver_info
+        );
+    }
+}
+
+fn create_offscreen_target(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+fn create_compute_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    file: ShaderFile,
+) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        module,
+        entry_point: match file {
+            ShaderFile::Compute | ShaderFile::Diagnostics => "main",
+            ShaderFile::Trace => "cs_trace",
+        },
+    })
+}
+
+fn create_output_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    hdr_framebuffer: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(hdr_framebuffer),
+        }],
+    })
 }
 
 impl Renderer {
-    async fn new(window: &Window) -> Self {
-        let instance = wgpu::Instance::default();
+    async fn new(window: &Window, settings: &RendererSettings) -> Result<Self, Error> {
+        let instance = create_instance(settings.backends);
         let size = window.inner_size();
-        let surface = unsafe { instance.create_surface(window) }.expect("Can't create surface");
+        let surface = unsafe { instance.create_surface(window) }.map_err(Error::Surface)?;
+        Self::with_surface(&instance, Some(surface), size.width, size.height, settings).await
+    }
+
+    async fn new_headless(
+        width: u32,
+        height: u32,
+        settings: &RendererSettings,
+    ) -> Result<Self, Error> {
+        let instance = create_instance(settings.backends);
+        Self::with_surface(&instance, None, width, height, settings).await
+    }
+
+    async fn with_surface(
+        instance: &wgpu::Instance,
+        surface: Option<wgpu::Surface>,
+        width: u32,
+        height: u32,
+        settings: &RendererSettings,
+    ) -> Result<Self, Error> {
+        // Resolved up front so it can be reported, e.g. in screenshots
+        let settings = &RendererSettings {
+            seed: Some(settings.seed.unwrap_or_else(|| thread_rng().gen())),
+            ..settings.clone()
+        };
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::HighPerformance,
-                force_fallback_adapter: false,
+                force_fallback_adapter: settings.force_fallback_adapter,
                 compatible_surface: None,
             })
             .await
-            .expect("Can't get an adapter.");
+            .ok_or(Error::NoAdapter)?;
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    features: wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY,
+                    features: adapter.features()
+                        & (wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY
+                            | wgpu::Features::TIMESTAMP_QUERY),
                     limits: adapter.limits(),
                 },
                 None,
             )
             .await
-            .expect("Can't get a device.");
+            .map_err(Error::Device)?;
+        let device_loss = DeviceLoss::watch(&device);
         //Buffers
         let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
@@ -111,30 +399,57 @@ impl Renderer {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
-        let spheres = init_spheres();
+        let spheres = settings
+            .spheres
+            .clone()
+            .unwrap_or_else(|| init_spheres(settings));
         let spheres_buffers = [
             device.create_buffer(&wgpu::BufferDescriptor {
                 label: None,
                 size: (mem::size_of::<Sphere>() * MAX_SPHERES_COUNT) as _,
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_DST
+                    | wgpu::BufferUsages::COPY_SRC,
                 mapped_at_creation: false,
             }),
             device.create_buffer(&wgpu::BufferDescriptor {
                 label: None,
                 size: (mem::size_of::<Sphere>() * MAX_SPHERES_COUNT) as _,
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_DST
+                    | wgpu::BufferUsages::COPY_SRC,
                 mapped_at_creation: false,
             }),
         ];
-        for i in 0..2 {
-            queue.write_buffer(&spheres_buffers[i], 0, bytemuck::cast_slice(&spheres));
+        for spheres_buffer in &spheres_buffers {
+            queue.write_buffer(spheres_buffer, 0, bytemuck::cast_slice(&spheres));
         }
+        let collision_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: analysis::COLLISION_LOG_SIZE as _,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let primitives_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: PRIMITIVES_BUFFER_SIZE as _,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(
+            &primitives_buffer,
+            0,
+            &primitives::buffer_contents(&settings.primitives),
+        );
         // Compute pipeline
+        let compute_source =
+            shaders::compose(include_str!("compute.wgsl")).map_err(Error::Shader)?;
+        layout::check(&compute_source).map_err(Error::Layout)?;
         let compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
-            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
-                "compute.wgsl"
-            ))),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Owned(compute_source)),
         });
         let compute_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -176,6 +491,28 @@ impl Renderer {
                         },
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                analysis::COLLISION_LOG_SIZE as _,
+                            ),
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(PRIMITIVES_BUFFER_SIZE as _),
+                        },
+                        count: None,
+                    },
                 ],
             });
         let compute_pipeline_layout =
@@ -184,12 +521,12 @@ impl Renderer {
                 bind_group_layouts: &[&compute_bind_group_layout],
                 push_constant_ranges: &[],
             });
-        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: None,
-            layout: Some(&compute_pipeline_layout),
-            module: &compute_shader,
-            entry_point: "main",
-        });
+        let compute_pipeline = create_compute_pipeline(
+            &device,
+            &compute_pipeline_layout,
+            &compute_shader,
+            ShaderFile::Compute,
+        );
         let compute_bind_groups = [
             device.create_bind_group(&wgpu::BindGroupDescriptor {
                 label: None,
@@ -207,6 +544,14 @@ impl Renderer {
                         binding: 2,
                         resource: spheres_buffers[1].as_entire_binding(),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: collision_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: primitives_buffer.as_entire_binding(),
+                    },
                 ],
             }),
             device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -225,22 +570,43 @@ impl Renderer {
                         binding: 2,
                         resource: spheres_buffers[0].as_entire_binding(),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: collision_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: primitives_buffer.as_entire_binding(),
+                    },
                 ],
             }),
         ];
-        // Graphics pipeline
+        let diagnostics_source =
+            shaders::compose(include_str!("diagnostics.wgsl")).map_err(Error::Shader)?;
+        layout::check(&diagnostics_source).map_err(Error::Layout)?;
+        let diagnostics_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Owned(diagnostics_source)),
+        });
+        let diagnostics = Diagnostics::new(
+            &device,
+            &diagnostics_shader,
+            &uniform_buffer,
+            &spheres_buffers,
+        );
+        // Ray tracing pipeline
+        let source = shaders::compose(include_str!("shader.wgsl")).map_err(Error::Shader)?;
+        layout::check(&source).map_err(Error::Layout)?;
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
-            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
-                "shader.wgsl"
-            ))),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Owned(source)),
         });
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::VERTEX,
+                    visibility: wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -252,7 +618,7 @@ impl Renderer {
                 },
                 wgpu::BindGroupLayoutEntry {
                     binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    visibility: wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Storage { read_only: true },
                         has_dynamic_offset: false,
@@ -262,9 +628,19 @@ impl Renderer {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(PRIMITIVES_BUFFER_SIZE as _),
+                    },
+                    count: None,
+                },
             ],
         });
-        let render_bind_groups = [
+        let trace_bind_groups = [
             device.create_bind_group(&wgpu::BindGroupDescriptor {
                 label: None,
                 layout: &bind_group_layout,
@@ -277,6 +653,10 @@ impl Renderer {
                         binding: 1,
                         resource: spheres_buffers[0].as_entire_binding(),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: primitives_buffer.as_entire_binding(),
+                    },
                 ],
             }),
             device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -291,136 +671,298 @@ impl Renderer {
                         binding: 1,
                         resource: spheres_buffers[1].as_entire_binding(),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: primitives_buffer.as_entire_binding(),
+                    },
                 ],
             }),
         ];
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[],
-        });
-        let swapchain_capabilities = surface.get_capabilities(&adapter);
-        let swapchain_format = swapchain_capabilities.formats[0];
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(swapchain_format.into())],
-            }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 4,
-                ..Default::default()
-            },
-            multiview: None,
-        });
+        let output_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: HDR_FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                }],
+            });
+        let trace_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&bind_group_layout, &output_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let trace_pipeline =
+            create_compute_pipeline(&device, &trace_pipeline_layout, &shader, ShaderFile::Trace);
+        let (swapchain_format, alpha_mode, present_mode) = match &surface {
+            Some(surface) => {
+                let swapchain_capabilities = surface.get_capabilities(&adapter);
+                let present_mode = if swapchain_capabilities
+                    .present_modes
+                    .contains(&settings.present_mode)
+                {
+                    settings.present_mode
+                } else {
+                    warn!(
+                        "Present mode {:?} isn't supported, falling back to Fifo",
+                        settings.present_mode
+                    );
+                    wgpu::PresentMode::Fifo
+                };
+                (
+                    tonemap::pick_surface_format(&swapchain_capabilities.formats),
+                    swapchain_capabilities.alpha_modes[0],
+                    present_mode,
+                )
+            }
+            None => (
+                OFFSCREEN_FORMAT,
+                wgpu::CompositeAlphaMode::Auto,
+                wgpu::PresentMode::Fifo,
+            ),
+        };
+        debug!("Surface format {:?}", swapchain_format);
+        let autofocus = AutoFocus::new(&device, &shader, &bind_group_layout);
 
         let surface_config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: swapchain_format,
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
-            alpha_mode: swapchain_capabilities.alpha_modes[0],
+            width,
+            height,
+            present_mode,
+            alpha_mode,
             view_formats: vec![],
         };
-        surface.configure(&device, &surface_config);
+        if let Some(surface) = &surface {
+            surface.configure(&device, &surface_config);
+        }
+        let offscreen = surface
+            .is_none()
+            .then(|| create_offscreen_target(&device, OFFSCREEN_FORMAT, width, height));
 
-        let multisampled_texture_extend = wgpu::Extent3d {
-            width: size.width,
-            height: size.height,
-            depth_or_array_layers: 1,
-        };
-        let multisampled_texture_descriptor = wgpu::TextureDescriptor {
-            label: None,
-            size: multisampled_texture_extend,
-            mip_level_count: 1,
-            sample_count: SAMPLE_COUNT,
-            dimension: wgpu::TextureDimension::D2,
-            format: swapchain_format,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            view_formats: &[],
+        let resolution_scaler = ResolutionScaler::new(
+            settings.target_fps,
+            settings.min_render_scale,
+            settings.max_render_scale,
+        );
+        let (render_width, render_height) = resolution_scaler.scaled_size(width, height);
+        let hdr_framebuffer = create_hdr_framebuffer(&device, render_width, render_height);
+        let output_bind_group =
+            create_output_bind_group(&device, &output_bind_group_layout, &hdr_framebuffer);
+        let post_processor =
+            PostProcessor::new(&device, &hdr_framebuffer, render_width, render_height);
+        let upscaler = Upscaler::new(&device, post_processor.output(), width, height);
+        let color_lut = match &settings.color_lut {
+            Some(path) => ColorLut::load(path),
+            None => ColorLut::parse_cube(include_str!("grade.cube")),
         };
+        let color_lut = color_lut.map_err(Error::ColorLut)?;
+        let tone_mapper = ToneMapper::new(
+            &device,
+            upscaler.output(),
+            color_lut.create_view(&device, &queue),
+            color_lut.domain(),
+            swapchain_format,
+        );
+        let mut post_effects = PostEffects::default();
+        // Asking for a LUT means wanting to see it
+        if settings.color_lut.is_some() {
+            post_effects.toggle(PostEffect::ColorGrading);
+        }
 
-        let multisampled_framebuffer = device
-            .create_texture(&multisampled_texture_descriptor)
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let profiler = Profiler::new(&device, &queue);
+        let spheres_count = spheres.len() as u32;
+        let snapshot = SphereSnapshot::new(&device, spheres);
 
-        Renderer {
+        Ok(Renderer {
             surface,
+            offscreen,
+            capture_target: None,
             surface_config,
+            adapter_info: adapter.get_info(),
             queue,
             device,
-            render_pipeline,
+            device_loss,
+            settings: settings.clone(),
+            trace_pipeline_layout,
+            trace_pipeline,
+            compute_pipeline_layout,
             compute_pipeline,
             uniform_buffer,
-            render_bind_groups,
+            spheres_buffers,
+            collision_buffer,
+            primitives_buffer,
+            diagnostics,
+            diagnostics_log: None,
+            diagnostics_interval: Some(DIAGNOSTICS_INTERVAL),
+            snapshot,
+            state_save: None,
+            recorder: None,
+            analysis: None,
+            game: None,
+            trace_bind_groups,
             compute_bind_groups,
-            multisampled_framebuffer,
+            output_bind_group_layout,
+            output_bind_group,
+            hdr_framebuffer,
+            post_processor,
+            post_effects,
+            upscaler,
+            tone_mapper,
+            autofocus,
+            screen_capture: ScreenCapture::new(),
+            screenshot: None,
+            captured_frame: None,
+            resolution_scaler,
+            frame_timer: FrameTimer::new(),
+            profiler,
+            gpu_frame_time: None,
             camera_x: 0.0f32,
-            aspect_ratio: (size.width as f32) / (size.height as f32),
-            spheres_count: spheres.len() as u32,
+            motion_blur: false,
+            shutter_angle: 180.0,
+            aperture: 0.0,
+            focus_distance: 5.0,
+            focus_point: [0.5, 0.5],
+            focus_requested: false,
+            frame_index: 0,
+            debug_view: DebugView::Off,
+            samples_per_pixel: settings.samples_per_pixel,
+            max_bounces: settings.max_bounces,
+            aspect_ratio: (width as f32) / (height as f32),
+            spheres_count,
+            time_step: None,
+            minimized: false,
+            started: Instant::now(),
             frame_start: Instant::now(),
-        }
+        })
     }
 
     fn resize(&mut self, width: u32, height: u32) {
+        self.minimized = width == 0 || height == 0;
+        if self.minimized {
+            return;
+        }
         self.surface_config.width = width;
         self.surface_config.height = height;
         self.aspect_ratio = (width as f32) / (height as f32);
-        self.surface.configure(&self.device, &self.surface_config);
-        let multisampled_texture_extend = wgpu::Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        };
-        let multisampled_texture_descriptor = wgpu::TextureDescriptor {
-            label: None,
-            size: multisampled_texture_extend,
-            mip_level_count: 1,
-            sample_count: SAMPLE_COUNT,
-            dimension: wgpu::TextureDimension::D2,
-            format: self.surface_config.format,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            view_formats: &[],
-        };
+        self.capture_target = None;
+        match &self.surface {
+            Some(surface) => surface.configure(&self.device, &self.surface_config),
+            None => {
+                self.offscreen = Some(create_offscreen_target(
+                    &self.device,
+                    OFFSCREEN_FORMAT,
+                    width,
+                    height,
+                ))
+            }
+        }
+        self.upscaler.resize(&self.device, width, height);
+        self.tone_mapper
+            .resize(&self.device, self.upscaler.output());
+        self.resize_render_targets();
+    }
 
-        self.multisampled_framebuffer = self
-            .device
-            .create_texture(&multisampled_texture_descriptor)
-            .create_view(&wgpu::TextureViewDescriptor::default());
-    }
-
-    fn render(&mut self, odd_frame: bool) {
-        let frame = self
-            .surface
-            .get_current_texture()
-            .expect("Can't get swap chain texture");
-        let view = frame
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+    // Everything before the upscaler runs at the scaled resolution
+    fn resize_render_targets(&mut self) {
+        let (width, height) = self
+            .resolution_scaler
+            .scaled_size(self.surface_config.width, self.surface_config.height);
+        self.hdr_framebuffer = create_hdr_framebuffer(&self.device, width, height);
+        self.output_bind_group = create_output_bind_group(
+            &self.device,
+            &self.output_bind_group_layout,
+            &self.hdr_framebuffer,
+        );
+        self.post_processor
+            .resize(&self.device, &self.hdr_framebuffer, width, height);
+        self.upscaler
+            .set_input(&self.device, self.post_processor.output());
+    }
+
+    // Only fails when there's no recovering, other surface errors skip the frame
+    fn render(&mut self, odd_frame: bool) -> Result<(), wgpu::SurfaceError> {
+        // Skipped frames don't move the spheres, the next one steps from now
+        if self.minimized {
+            self.frame_start = Instant::now();
+            return Ok(());
+        }
+        let frame = match &self.surface {
+            Some(surface) => match surface.get_current_texture() {
+                Ok(frame) => Some(frame),
+                Err(err @ (wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated)) => {
+                    debug!("Reconfiguring the surface: {}", err);
+                    surface.configure(&self.device, &self.surface_config);
+                    self.frame_start = Instant::now();
+                    return Ok(());
+                }
+                Err(wgpu::SurfaceError::Timeout) => {
+                    warn!("Timed out waiting for the surface, skipping the frame");
+                    self.frame_start = Instant::now();
+                    return Ok(());
+                }
+                Err(err) => return Err(err),
+            },
+            None => None,
+        };
+        let view = match &frame {
+            Some(frame) => &frame.texture,
+            None => self
+                .offscreen
+                .as_ref()
+                .expect("Can't render without a surface or an offscreen target"),
+        }
+        .create_view(&wgpu::TextureViewDescriptor::default());
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.profiler.begin_frame(&mut encoder);
+        let frame_time = self
+            .time_step
+            .unwrap_or_else(|| self.frame_start.elapsed().as_secs_f32().min(MAX_FRAME_TIME));
+        let time_elapsed = self.update_game(frame_time);
         self.queue.write_buffer(
             &self.uniform_buffer,
             0,
             bytemuck::cast_slice(&[RayTracingParams {
-                camera_pos: [self.camera_x, 0.0, -1.0, 0.0],
+                camera_pos: self.camera_pos(),
+                ball: self.game.as_ref().map_or(Sphere::zeroed(), Game::ball),
+                player_paddle: self.game.as_ref().map_or([0.0; 4], Game::player_paddle),
+                opponent_paddle: self.game.as_ref().map_or([0.0; 4], Game::opponent_paddle),
                 aspect_ratio: self.aspect_ratio,
-                spheres_count: self.spheres_count as u32,
-                time_elapsed: self.frame_start.elapsed().as_secs_f32(),
-                _padding: 0.0,
+                spheres_count: self.spheres_count,
+                time_elapsed,
+                shutter_time: if self.motion_blur {
+                    time_elapsed * self.shutter_angle / 360.0
+                } else {
+                    0.0
+                },
+                samples_per_pixel: self.samples_per_pixel,
+                aperture: self.aperture,
+                focus_distance: self.focus_distance,
+                frame_index: self.frame_index,
+                focus_point: self.focus_point,
+                debug_view: self.debug_view as u32,
+                max_bounces: self.max_bounces,
             }]),
         );
+        let tick = self.frame_index;
+        self.frame_index = self.frame_index.wrapping_add(1);
+        if let Some(recorder) = &mut self.recorder {
+            recorder.tick(frame_time, odd_frame);
+        }
+        // Only the counter, the events past it are overwritten
+        encoder.clear_buffer(
+            &self.collision_buffer,
+            0,
+            wgpu::BufferSize::new(mem::size_of::<u32>() as _),
+        );
         {
             let mut cpass =
                 encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
@@ -432,112 +974,1105 @@ impl Renderer {
             );
             cpass.dispatch_workgroups(self.spheres_count, 1, 1);
         }
+        self.profiler.end_pass(&mut encoder, ProfiledPass::Physics);
+        if self.diagnostics_log.is_some()
+            || self
+                .diagnostics_interval
+                .is_some_and(|interval| tick.is_multiple_of(interval))
         {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &self.multisampled_framebuffer,
-                    resolve_target: Some(&view),
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
-                        store: false,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
-            rpass.set_pipeline(&self.render_pipeline);
-            rpass.set_bind_group(
+            self.diagnostics.reduce(&mut encoder, odd_frame);
+        }
+        self.profiler
+            .end_pass(&mut encoder, ProfiledPass::Diagnostics);
+        if self.snapshot.copy(
+            &mut encoder,
+            &self.spheres_buffers[if odd_frame { 0 } else { 1 }],
+            self.spheres_count,
+            self.frame_index,
+        ) {
+            // The rest of the state is taken from the frame the spheres are from
+            let state = self.simulation_state();
+            if let Some((_, pending)) = &mut self.state_save {
+                *pending = Some(state);
+            }
+        }
+        if let Some(analysis) = &mut self.analysis {
+            analysis.copy(
+                &mut encoder,
+                &self.spheres_buffers[if odd_frame { 0 } else { 1 }],
+                &self.collision_buffer,
+                self.spheres_count,
+                tick,
+                time_elapsed,
+            );
+        }
+        {
+            let mut cpass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            cpass.set_pipeline(&self.trace_pipeline);
+            cpass.set_bind_group(
                 0,
-                &(self.render_bind_groups[if odd_frame { 1 } else { 0 }]),
+                &(self.trace_bind_groups[if odd_frame { 1 } else { 0 }]),
                 &[],
             );
-            rpass.draw(0..6, 0..1);
+            cpass.set_bind_group(1, &self.output_bind_group, &[]);
+            let (width, height) = self
+                .resolution_scaler
+                .scaled_size(self.surface_config.width, self.surface_config.height);
+            cpass.dispatch_workgroups(width.div_ceil(TILE_SIZE), height.div_ceil(TILE_SIZE), 1);
+        }
+        self.profiler.end_pass(&mut encoder, ProfiledPass::Trace);
+        if self.focus_requested
+            && self.autofocus.request(
+                &mut encoder,
+                &self.trace_bind_groups[if odd_frame { 1 } else { 0 }],
+            )
+        {
+            self.focus_requested = false;
+        }
+        let debugging = self.debug_view != DebugView::Off;
+        let post_effects = if debugging {
+            PostEffects::none()
+        } else {
+            self.post_effects
+        };
+        self.post_processor
+            .render(&self.queue, &mut encoder, post_effects);
+        self.upscaler.render(&self.queue, &mut encoder);
+        self.profiler
+            .end_pass(&mut encoder, ProfiledPass::PostProcess);
+        self.tone_mapper.render(
+            &self.queue,
+            &mut encoder,
+            &view,
+            post_effects,
+            self.started.elapsed().as_secs_f32(),
+            debugging,
+        );
+        self.profiler.end_pass(&mut encoder, ProfiledPass::ToneMap);
+        if self.screen_capture.is_requested() {
+            let (source, format) = match &self.offscreen {
+                Some(offscreen) => (offscreen, OFFSCREEN_FORMAT),
+                None => {
+                    let format = tonemap::capture_format(self.surface_config.format);
+                    let capture_target = self.capture_target.get_or_insert_with(|| {
+                        create_offscreen_target(
+                            &self.device,
+                            format,
+                            self.surface_config.width,
+                            self.surface_config.height,
+                        )
+                    });
+                    self.tone_mapper.render_capture(
+                        &mut encoder,
+                        &capture_target.create_view(&wgpu::TextureViewDescriptor::default()),
+                    );
+                    (&*capture_target, format)
+                }
+            };
+            if let Err(err) = self.screen_capture.copy(
+                &self.device,
+                &mut encoder,
+                source,
+                format,
+                self.surface_config.width,
+                self.surface_config.height,
+            ) {
+                error!("{}", err);
+                self.screenshot = None;
+            }
+        }
+        self.profiler.resolve(&mut encoder);
+        // The caller rebuilds everything on a lost device, the frame is dropped
+        if !self.frame_timer.submit(
+            &self.device_loss,
+            &self.device,
+            &self.queue,
+            encoder.finish(),
+        ) {
+            self.frame_start = Instant::now();
+            return Ok(());
+        }
+        self.autofocus.start_readback();
+        self.screen_capture.start_readback();
+        self.snapshot.start_readback();
+        self.diagnostics.start_readback();
+        if let Some(analysis) = &mut self.analysis {
+            analysis.start_readback();
+        }
+        self.profiler.start_readback();
+        if !self.device_loss.poll(&self.device, wgpu::Maintain::Poll) {
+            self.frame_start = Instant::now();
+            return Ok(());
+        }
+        self.poll_readbacks();
+        if let Some(frame) = frame {
+            frame.present();
         }
-        self.queue.submit(Some(encoder.finish()));
-        frame.present();
         self.frame_start = Instant::now();
+        Ok(())
     }
 
-    fn move_x(&mut self, delta: f32) {
-        self.camera_x += delta;
-    }
-}
-
-async fn run() {
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new()
-        .build(&event_loop)
-        .expect("Can't create window");
-    let mut frames_start = Instant::now();
-    let mut frame_count = 0;
-    let mut renderer = Renderer::new(&window).await;
-    event_loop.run(move |event, _, control_flow| match event {
-        Event::WindowEvent {
-            ref event,
-            window_id,
-        } if window_id == window.id() => match event {
-            WindowEvent::CloseRequested
-            | WindowEvent::KeyboardInput {
-                input:
-                    KeyboardInput {
-                        state: ElementState::Pressed,
-                        virtual_keycode: Some(VirtualKeyCode::Escape),
-                        ..
-                    },
-                ..
-            } => *control_flow = ControlFlow::Exit,
-            WindowEvent::Resized(size) => {
-                renderer.resize(size.width, size.height);
+    // Picks up whatever the GPU finished since the last call
+    fn poll_readbacks(&mut self) {
+        if let Some(distance) = self.autofocus.poll() {
+            self.focus_distance = distance.max(MIN_FOCUS_DISTANCE);
+            info!("Focus distance {}", self.focus_distance);
+            if let Some(recorder) = &mut self.recorder {
+                recorder.record(Input::Focus {
+                    point: self.focus_point,
+                    distance: self.focus_distance,
+                });
             }
-            WindowEvent::KeyboardInput {
-                input:
-                    KeyboardInput {
-                        state: ElementState::Pressed,
-                        virtual_keycode: Some(VirtualKeyCode::Left),
-                        ..
-                    },
-                ..
-            } => {
-                renderer.move_x(0.1);
-                renderer.render(frame_count % 2 == 1);
+        }
+        if self.snapshot.poll() {
+            if let Some((path, Some(mut state))) = self.state_save.take() {
+                state.spheres = self.snapshot.spheres().to_vec();
+                state.spheres_count = state.spheres.len() as u32;
+                state.frame_index = self.snapshot.frame();
+                match state.save(&path) {
+                    Ok(()) => info!("Saved the simulation state to {}", path.display()),
+                    Err(err) => error!("{}", err),
+                }
             }
-            WindowEvent::KeyboardInput {
-                input:
-                    KeyboardInput {
-                        state: ElementState::Pressed,
-                        virtual_keycode: Some(VirtualKeyCode::Right),
-                        ..
-                    },
-                ..
-            } => {
-                renderer.move_x(-0.1);
-                renderer.render(frame_count % 2 == 1);
+        }
+        let diagnostics = self.diagnostics.poll();
+        if let Some(diagnostics_log) = &mut self.diagnostics_log {
+            if let Err(err) = diagnostics
+                .iter()
+                .try_for_each(|diagnostics| diagnostics_log.write(diagnostics))
+            {
+                error!("Can't write the diagnostics, stopping them: {}", err);
+                self.diagnostics_log = None;
             }
-            _ => {}
-        },
-        Event::RedrawRequested(_) => {
-            frame_count += 1;
-            if frame_count >= 1000 {
-                let elapsed_time = frames_start.elapsed().as_secs_f32();
-                println!(
-                    "Avg frame time {}ms, {} fps",
-                    elapsed_time * 1000.0 / frame_count as f32,
-                    frame_count as f32 / elapsed_time
-                );
-                frame_count = 0;
-                frames_start = Instant::now();
+        }
+        if let Some(analysis) = &mut self.analysis {
+            if let Err(err) = analysis.poll() {
+                error!("Can't write the analysis, stopping it: {}", err);
+                self.analysis = None;
             }
-            renderer.render(frame_count % 2 == 1);
         }
-        Event::RedrawEventsCleared => {
-            window.request_redraw();
+        if let Some(image) = self.screen_capture.poll() {
+            match self.screenshot.take() {
+                Some((path, metadata)) => match image.write_png(&path, &metadata) {
+                    Ok(()) => info!("Saved a screenshot to {}", path.display()),
+                    Err(err) => error!("{}", err),
+                },
+                None => self.captured_frame = Some(image),
+            }
         }
-        _ => {}
-    });
-}
+        let submitted_frame_time = self.frame_timer.poll();
+        if let Some(frame_time) = submitted_frame_time {
+            self.profiler.push_submitted_frame_time(frame_time);
+        }
+        let profiled_frame_time = self.profiler.poll();
+        // Timestamps are more precise when there are any
+        let Some(frame_time) = profiled_frame_time.or(submitted_frame_time) else {
+            return;
+        };
+        self.gpu_frame_time = Some(frame_time);
+        if self.resolution_scaler.update(frame_time) {
+            self.resize_render_targets();
+            info!("Render scale {}", self.resolution_scaler.scale());
+        }
+    }
 
-fn main() {
-    tracing_subscriber::fmt::init();
-    debug!("Starting");
-    run().block_on();
+    // Requests a new device and rebuilds everything on it, the spheres continue
+    // from the last snapshot and the view settings carry over
+    async fn recover(&mut self, window: &Window) -> Result<(), Error> {
+        let settings = RendererSettings {
+            spheres: Some(self.snapshot.spheres().to_vec()),
+            ..self.settings.clone()
+        };
+        // Some platforms allow only one surface per window
+        self.surface = None;
+        let mut renderer = Renderer::new(window, &settings).await?;
+        renderer.settings.spheres = None;
+        renderer.post_effects = self.post_effects;
+        renderer.tone_mapper.operator = self.tone_mapper.operator;
+        renderer.tone_mapper.exposure = self.tone_mapper.exposure;
+        renderer.upscaler.filter = self.upscaler.filter;
+        renderer.resolution_scaler.enabled = self.resolution_scaler.enabled;
+        renderer.camera_x = self.camera_x;
+        renderer.motion_blur = self.motion_blur;
+        renderer.shutter_angle = self.shutter_angle;
+        renderer.aperture = self.aperture;
+        renderer.focus_distance = self.focus_distance;
+        renderer.focus_point = self.focus_point;
+        renderer.frame_index = self.frame_index;
+        renderer
+            .snapshot
+            .reset(self.snapshot.spheres().to_vec(), self.snapshot.frame());
+        renderer.debug_view = self.debug_view;
+        renderer.samples_per_pixel = self.samples_per_pixel;
+        renderer.max_bounces = self.max_bounces;
+        renderer.time_step = self.time_step;
+        renderer.started = self.started;
+        renderer.resize_render_targets();
+        renderer.recorder = self.recorder.take();
+        renderer.analysis = self.analysis.take();
+        renderer.diagnostics_log = self.diagnostics_log.take();
+        renderer.diagnostics_interval = self.diagnostics_interval;
+        renderer.game = self.game.take();
+        if let Some(analysis) = &mut renderer.analysis {
+            analysis.recreate_buffers(&renderer.device);
+        }
+        *self = renderer;
+        // The spheres went back to the snapshot, a replay has to do the same
+        let state = self.simulation_state();
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(Input::LoadState(Box::new(state)));
+        }
+        info!(
+            "Recovered on {}, the spheres went back {} frames to the snapshot of frame {}",
+            self.adapter_info.name,
+            self.frame_index.wrapping_sub(self.snapshot.frame()),
+            self.snapshot.frame()
+        );
+        Ok(())
+    }
+
+    // The running pipelines are only replaced if the new source validates
+    fn reload_shader(&mut self, file: ShaderFile, source: &str, includes: &[(&str, &str)]) {
+        let composed = shaders::compose_with(source, includes)
+            .and_then(|source| layout::check(&source).map(|()| source));
+        let source = match composed {
+            Ok(source) => source,
+            Err(err) => {
+                error!("Can't reload {}, keeping the old shader:\n{}", file, err);
+                return;
+            }
+        };
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Owned(source)),
+            });
+        let layout = match file {
+            ShaderFile::Compute => &self.compute_pipeline_layout,
+            ShaderFile::Trace => &self.trace_pipeline_layout,
+            ShaderFile::Diagnostics => self.diagnostics.pipeline_layout(),
+        };
+        let pipeline = create_compute_pipeline(&self.device, layout, &shader, file);
+        let autofocus_pipeline = (file == ShaderFile::Trace)
+            .then(|| self.autofocus.create_pipeline(&self.device, &shader));
+        if let Some(err) = self.device.pop_error_scope().block_on() {
+            error!("Can't reload {}, keeping the old shader:\n{}", file, err);
+            return;
+        }
+        match file {
+            ShaderFile::Compute => self.compute_pipeline = pipeline,
+            ShaderFile::Trace => self.trace_pipeline = pipeline,
+            ShaderFile::Diagnostics => self.diagnostics.set_pipeline(pipeline),
+        }
+        if let Some(autofocus_pipeline) = autofocus_pipeline {
+            self.autofocus.set_pipeline(autofocus_pipeline);
+        }
+        info!("Reloaded {}", file);
+    }
+
+    fn camera_pos(&self) -> [f32; 4] {
+        if self.game.is_some() {
+            [0.0, 0.0, GAME_CAMERA_Z, 0.0]
+        } else {
+            [self.camera_x, 0.0, -1.0, 0.0]
+        }
+    }
+
+    // Saves the next full resolution frame as a PNG, along with what it takes
+    // to render it again
+    fn screenshot(&mut self, path: PathBuf) {
+        if self.screen_capture.request() {
+            self.screenshot = Some((path, self.metadata()));
+        } else {
+            warn!("Still saving the previous screenshot");
+        }
+    }
+
+    // Keeps the next full resolution frame in `captured_frame`
+    fn capture_frame(&mut self) -> bool {
+        self.screen_capture.request()
+    }
+
+    fn metadata(&self) -> Vec<(String, String)> {
+        [
+            ("Software", "wroom ten".to_owned()),
+            (
+                "Seed",
+                format!("{}", self.settings.seed.unwrap_or_default()),
+            ),
+            ("Spheres", self.spheres_count.to_string()),
+            ("Camera", format!("{:?}", self.camera_pos())),
+            ("Aperture", self.aperture.to_string()),
+            ("Focus distance", self.focus_distance.to_string()),
+            ("Frame", self.frame_index.to_string()),
+        ]
+        .map(|(keyword, text)| (keyword.to_owned(), text))
+        .to_vec()
+    }
+
+    // Writes the simulation state after the next frame to a file
+    fn save_state(&mut self, path: PathBuf) {
+        if self.state_save.is_some() {
+            warn!("Still saving the previous simulation state");
+            return;
+        }
+        // Filled in when the next frame copies the spheres
+        self.snapshot.request();
+        self.state_save = Some((path, None));
+    }
+
+    // The current settings with the spheres of the last snapshot
+    fn simulation_state(&self) -> SimulationState {
+        SimulationState {
+            version: STATE_VERSION,
+            seed: self.settings.seed,
+            frame_index: self.frame_index,
+            camera_x: self.camera_x,
+            motion_blur: self.motion_blur,
+            shutter_angle: self.shutter_angle,
+            aperture: self.aperture,
+            focus_distance: self.focus_distance,
+            samples_per_pixel: self.samples_per_pixel,
+            max_bounces: self.max_bounces,
+            spheres_count: self.snapshot.spheres().len() as u32,
+            spheres: self.snapshot.spheres().to_vec(),
+            primitives: self.settings.primitives.clone(),
+            game: self.game.clone(),
+        }
+    }
+
+    fn load_state(&mut self, state: SimulationState) {
+        for spheres_buffer in &self.spheres_buffers {
+            self.queue
+                .write_buffer(spheres_buffer, 0, bytemuck::cast_slice(&state.spheres));
+        }
+        self.queue.write_buffer(
+            &self.primitives_buffer,
+            0,
+            &primitives::buffer_contents(&state.primitives),
+        );
+        self.settings.primitives = state.primitives;
+        self.spheres_count = state.spheres_count;
+        self.settings.seed = state.seed;
+        self.frame_index = state.frame_index;
+        self.camera_x = state.camera_x;
+        self.motion_blur = state.motion_blur;
+        self.shutter_angle = state.shutter_angle;
+        self.aperture = state.aperture;
+        self.focus_distance = state.focus_distance;
+        self.samples_per_pixel = state.samples_per_pixel;
+        self.max_bounces = state.max_bounces;
+        self.snapshot.reset(state.spheres, state.frame_index);
+        self.state_save = None;
+        if state.game.is_some() {
+            self.game = state.game;
+        }
+    }
+
+    // Records every input from here on, written out by `finish_recording`
+    fn start_recording(&mut self, path: PathBuf) {
+        self.recorder = Some(Recorder::new(
+            path,
+            ReplaySettings {
+                seed: self.settings.seed.expect("Can't record without a seed"),
+                spheres_count: self.spheres_count,
+                samples_per_pixel: self.samples_per_pixel,
+                max_bounces: self.max_bounces,
+                width: self.surface_config.width,
+                height: self.surface_config.height,
+                game: self.game.is_some(),
+                primitives: self.settings.primitives.clone(),
+            },
+        ));
+    }
+
+    fn finish_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            match recorder.finish() {
+                Ok((path, ticks)) => info!("Recorded {} ticks to {}", ticks, path.display()),
+                Err(err) => error!("{}", err),
+            }
+        }
+    }
+
+    // Totals of the spheres after the last physics step that has been read back
+    fn physics_diagnostics(&self) -> Option<PhysicsDiagnostics> {
+        self.diagnostics.latest()
+    }
+
+    // Writes the physics diagnostics of every tick to a CSV file until
+    // `finish_logs`
+    fn start_diagnostics_log(&mut self, path: &Path) -> std::io::Result<()> {
+        self.diagnostics_log = Some(DiagnosticsLog::create(path)?);
+        info!("Writing the physics diagnostics to {}", path.display());
+        Ok(())
+    }
+
+    // Streams the spheres and collisions of every tick to CSV files in the
+    // directory until `finish_logs`
+    fn start_analysis(&mut self, directory: &Path) -> std::io::Result<()> {
+        self.analysis = Some(AnalysisLog::create(&self.device, directory)?);
+        info!("Writing the analysis to {}", directory.display());
+        Ok(())
+    }
+
+    // Renders a frame without a surface and waits for its readbacks, for the
+    // headless commands, which don't recover from a device loss
+    fn render_and_wait(&mut self, odd_frame: bool) -> Result<(), String> {
+        self.render(odd_frame).map_err(|err| err.to_string())?;
+        if !self.device_loss.poll(&self.device, wgpu::Maintain::Wait) {
+            return Err("The device was lost".to_owned());
+        }
+        self.poll_readbacks();
+        Ok(())
+    }
+
+    // Waits for the ticks still in flight before closing the files
+    fn finish_logs(&mut self) {
+        if self.device_loss.poll(&self.device, wgpu::Maintain::Wait) {
+            self.poll_readbacks();
+        }
+        if let Some(diagnostics_log) = self.diagnostics_log.take() {
+            if let Err(err) = diagnostics_log.finish() {
+                error!("Can't write the diagnostics: {}", err);
+            }
+        }
+        if let Some(analysis) = self.analysis.take() {
+            match analysis.finish() {
+                Ok(Dropped {
+                    ticks: 0,
+                    collisions: 0,
+                }) => info!("Finished the analysis"),
+                Ok(dropped) => warn!(
+                    "Finished the analysis, {} ticks were dropped because the readbacks fell behind and {} collisions because the log was full",
+                    dropped.ticks, dropped.collisions
+                ),
+                Err(err) => error!("Can't write the analysis: {}", err),
+            }
+        }
+    }
+
+    // Inputs go through here so a recording sees them in order with the ticks
+    fn apply(&mut self, input: Input) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(input.clone());
+        }
+        match input {
+            Input::MoveCamera(delta) => self.move_x(delta),
+            Input::Resize { width, height } => self.resize(width, height),
+            Input::CycleToneMapOperator => self.cycle_tone_map_operator(),
+            Input::ScaleExposure(factor) => self.scale_exposure(factor),
+            Input::TogglePostEffect(effect) => self.toggle_post_effect(effect),
+            Input::ToggleMotionBlur => self.toggle_motion_blur(),
+            Input::AdjustShutterAngle(delta) => self.adjust_shutter_angle(delta),
+            Input::AdjustAperture(delta) => self.adjust_aperture(delta),
+            Input::Focus { point, distance } => {
+                self.focus_point = point;
+                self.focus_distance = distance;
+            }
+            Input::CycleSamplesPerPixel => self.cycle_samples_per_pixel(),
+            Input::ToggleDynamicResolution => self.toggle_dynamic_resolution(),
+            Input::CycleUpscaleFilter => self.cycle_upscale_filter(),
+            Input::CycleDebugView => self.cycle_debug_view(),
+            Input::LoadState(state) => self.load_state(*state),
+            Input::ToggleGame => {
+                if let Some(game) = &mut self.game {
+                    game.toggle();
+                    info!("{}", game);
+                }
+            }
+            Input::SteerPaddle(direction) => {
+                if let Some(game) = &mut self.game {
+                    game.steer(direction);
+                }
+            }
+            Input::MovePaddle(target) => {
+                if let Some(game) = &mut self.game {
+                    game.move_player(target);
+                }
+            }
+        }
+    }
+
+    // Paddles and a ball for the player, Space serves the first point
+    fn start_game(&mut self) {
+        let game = Game::new();
+        info!("{}", game);
+        self.game = Some(game);
+    }
+
+    // Plays the game on for the frame time and returns the time the spheres
+    // move on for, a paused game holds them still too
+    fn update_game(&mut self, frame_time: f32) -> f32 {
+        let Some(game) = &mut self.game else {
+            return frame_time;
+        };
+        if let Some(scorer) = game.update(frame_time) {
+            info!("{:?} scored, {}", scorer, game);
+        }
+        if game.state() == GameState::Paused {
+            0.0
+        } else {
+            frame_time
+        }
+    }
+
+    // Where the cursor points in the plane the player's paddle moves in, the
+    // screen is a unit in front of the camera as in make_start_ray_for_point
+    fn paddle_target(&self, x: f64, y: f64) -> Option<[f32; 2]> {
+        self.game.as_ref()?;
+        let camera = self.camera_pos();
+        let point = [
+            (x / self.surface_config.width as f64) as f32 - 0.5,
+            (0.5 - (y / self.surface_config.height as f64) as f32) / self.aspect_ratio,
+        ];
+        let distance = PLAYER_PADDLE_Z - camera[2];
+        Some([0, 1].map(|i| camera[i] + (point[i] - camera[i]) * distance))
+    }
+
+    fn move_x(&mut self, delta: f32) {
+        self.camera_x += delta;
+    }
+
+    fn cycle_tone_map_operator(&mut self) {
+        self.tone_mapper.operator = self.tone_mapper.operator.next();
+        info!("Tone mapping operator {:?}", self.tone_mapper.operator);
+    }
+
+    fn scale_exposure(&mut self, factor: f32) {
+        self.tone_mapper.exposure *= factor;
+        info!("Exposure {}", self.tone_mapper.exposure);
+    }
+
+    fn toggle_motion_blur(&mut self) {
+        self.motion_blur = !self.motion_blur;
+        info!(
+            "Motion blur {}",
+            if self.motion_blur { "on" } else { "off" }
+        );
+    }
+
+    fn adjust_shutter_angle(&mut self, delta: f32) {
+        self.shutter_angle = (self.shutter_angle + delta).clamp(0.0, 360.0);
+        info!("Shutter angle {}", self.shutter_angle);
+    }
+
+    fn adjust_aperture(&mut self, delta: f32) {
+        self.aperture = (self.aperture + delta).clamp(0.0, 0.5);
+        info!("Aperture {}", self.aperture);
+    }
+
+    fn focus_at(&mut self, x: f64, y: f64) {
+        self.focus_point = [
+            (x / self.surface_config.width as f64) as f32,
+            (y / self.surface_config.height as f64) as f32,
+        ];
+        self.focus_requested = true;
+    }
+
+    fn cycle_samples_per_pixel(&mut self) {
+        let current = SAMPLES_PER_PIXEL
+            .iter()
+            .position(|&samples| samples == self.samples_per_pixel)
+            .unwrap_or(0);
+        self.samples_per_pixel = SAMPLES_PER_PIXEL[(current + 1) % SAMPLES_PER_PIXEL.len()];
+        info!("Samples per pixel {}", self.samples_per_pixel);
+    }
+
+    fn toggle_dynamic_resolution(&mut self) {
+        let enabled = self.resolution_scaler.toggle();
+        self.resize_render_targets();
+        info!("Dynamic resolution {}", if enabled { "on" } else { "off" });
+    }
+
+    fn cycle_upscale_filter(&mut self) {
+        self.upscaler.filter = self.upscaler.filter.next();
+        info!("Upscale filter {:?}", self.upscaler.filter);
+    }
+
+    fn cycle_debug_view(&mut self) {
+        self.debug_view = self.debug_view.next();
+        info!("Debug view {:?}", self.debug_view);
+    }
+
+    fn toggle_post_effect(&mut self, effect: PostEffect) {
+        let enabled = self.post_effects.toggle(effect);
+        info!("{:?} {}", effect, if enabled { "on" } else { "off" });
+    }
+}
+
+fn render_or_exit(
+    renderer: &mut Renderer,
+    window: &Window,
+    odd_frame: bool,
+    control_flow: &mut ControlFlow,
+) {
+    let result = renderer.render(odd_frame);
+    if renderer.device_loss.is_lost() {
+        warn!("Device lost, rebuilding the renderer");
+        if let Err(err) = renderer.recover(window).block_on() {
+            error!("Can't recover from the device loss: {}", err);
+            *control_flow = ControlFlow::Exit;
+        }
+    } else if let Err(err) = result {
+        error!("Can't render: {}", err);
+        *control_flow = ControlFlow::Exit;
+    }
+}
+
+async fn run(cli: &Cli) -> Result<(), Error> {
+    let event_loop = EventLoop::new();
+    let mut window_builder = WindowBuilder::new().with_title("wroom ten");
+    if let Some(resolution) = cli.resolution {
+        window_builder =
+            window_builder.with_inner_size(PhysicalSize::new(resolution.width, resolution.height));
+    }
+    if cli.fullscreen {
+        window_builder = window_builder.with_fullscreen(Some(Fullscreen::Borderless(None)));
+    }
+    let window = window_builder.build(&event_loop).map_err(Error::Window)?;
+    let mut frames_start = Instant::now();
+    let mut frame_count = 0;
+    let mut cursor_position = PhysicalPosition::new(0.0, 0.0);
+    // Held left, right, up and down arrows steer the paddle in a game
+    let mut arrows = [false; 4];
+    let mut steering = [0.0; 2];
+    let mut title = String::new();
+    let primitives = match &cli.scene {
+        Some(path) => Scene::load(path).map_err(Error::Scene)?.primitives,
+        None => Vec::new(),
+    };
+    let settings = RendererSettings {
+        primitives,
+        ..cli.renderer_settings()
+    };
+    let mut renderer = Renderer::new(&window, &settings).await?;
+    let mut shader_watcher = cli.watch_shaders.then(ShaderWatcher::new);
+    if cli.game {
+        renderer.start_game();
+    }
+    if let Some(path) = &cli.record {
+        renderer.start_recording(path.clone());
+    }
+    if let Some(path) = &cli.diagnostics {
+        renderer
+            .start_diagnostics_log(path)
+            .map_err(|err| Error::Diagnostics(path.clone(), err))?;
+    }
+    if let Some(directory) = &cli.analysis {
+        renderer
+            .start_analysis(directory)
+            .map_err(|err| Error::Analysis(directory.clone(), err))?;
+    }
+    let mut last_state = cli.state.clone();
+    if let Some(path) = &cli.state {
+        let state = SimulationState::load(path).map_err(Error::State)?;
+        renderer.apply(Input::LoadState(Box::new(state)));
+    }
+    event_loop.run(move |event, _, control_flow| match event {
+        Event::WindowEvent {
+            ref event,
+            window_id,
+        } if window_id == window.id() => match event {
+            WindowEvent::CloseRequested
+            | WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Escape),
+                        ..
+                    },
+                ..
+            } => *control_flow = ControlFlow::Exit,
+            WindowEvent::Resized(size) => {
+                renderer.apply(Input::Resize {
+                    width: size.width,
+                    height: size.height,
+                });
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode:
+                            Some(
+                                key @ (VirtualKeyCode::Left
+                                | VirtualKeyCode::Right
+                                | VirtualKeyCode::Up
+                                | VirtualKeyCode::Down),
+                            ),
+                        ..
+                    },
+                ..
+            } if renderer.game.is_some() => {
+                let arrow = match key {
+                    VirtualKeyCode::Left => 0,
+                    VirtualKeyCode::Right => 1,
+                    VirtualKeyCode::Up => 2,
+                    _ => 3,
+                };
+                arrows[arrow] = *state == ElementState::Pressed;
+                let direction = [
+                    arrows[1] as i32 - arrows[0] as i32,
+                    arrows[2] as i32 - arrows[3] as i32,
+                ]
+                .map(|d| d as f32);
+                // Key repeats don't change anything
+                if direction != steering {
+                    steering = direction;
+                    renderer.apply(Input::SteerPaddle(direction));
+                }
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Space),
+                        ..
+                    },
+                ..
+            } if renderer.game.is_some() => {
+                renderer.apply(Input::ToggleGame);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Left),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::MoveCamera(0.1));
+                render_or_exit(&mut renderer, &window, frame_count % 2 == 1, control_flow);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Right),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::MoveCamera(-0.1));
+                render_or_exit(&mut renderer, &window, frame_count % 2 == 1, control_flow);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::T),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::CycleToneMapOperator);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Equals),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::ScaleExposure(1.25));
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Minus),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::ScaleExposure(0.8));
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::B),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::TogglePostEffect(PostEffect::Bloom));
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::V),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::TogglePostEffect(PostEffect::Vignette));
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::C),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::TogglePostEffect(PostEffect::ChromaticAberration));
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::G),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::TogglePostEffect(PostEffect::FilmGrain));
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::L),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::TogglePostEffect(PostEffect::ColorGrading));
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::M),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::ToggleMotionBlur);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::LBracket),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::AdjustShutterAngle(-45.0));
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::RBracket),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::AdjustShutterAngle(45.0));
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Comma),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::AdjustAperture(-0.01));
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Period),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::AdjustAperture(0.01));
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::D),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::CycleDebugView);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::S),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::CycleSamplesPerPixel);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::R),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::ToggleDynamicResolution);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::U),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.apply(Input::CycleUpscaleFilter);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::P),
+                        ..
+                    },
+                ..
+            } => {
+                renderer.screenshot(screenshot::timestamped_path(Path::new("."), "png"));
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::F5),
+                        ..
+                    },
+                ..
+            } => {
+                let path = screenshot::timestamped_path(Path::new("."), "json");
+                renderer.save_state(path.clone());
+                last_state = Some(path);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::F9),
+                        ..
+                    },
+                ..
+            } => match last_state.as_deref().map(SimulationState::load) {
+                Some(Ok(state)) => {
+                    renderer.apply(Input::LoadState(Box::new(state)));
+                    info!("Loaded the simulation state");
+                }
+                Some(Err(err)) => error!("{}", err),
+                None => warn!("No simulation state to load, save one with F5 first"),
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                cursor_position = *position;
+                if let Some(target) = renderer.paddle_target(position.x, position.y) {
+                    renderer.apply(Input::MovePaddle(target));
+                }
+            }
+            // The mouse moves the paddle in a game
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } if renderer.game.is_none() => {
+                renderer.focus_at(cursor_position.x, cursor_position.y);
+            }
+            _ => {}
+        },
+        Event::RedrawRequested(_) => {
+            frame_count += 1;
+            if frame_count >= 1000 {
+                let elapsed_time = frames_start.elapsed().as_secs_f32();
+                println!(
+                    "Avg frame time {}ms, {} fps",
+                    elapsed_time * 1000.0 / frame_count as f32,
+                    frame_count as f32 / elapsed_time
+                );
+                renderer.profiler.log();
+                if let Some(diagnostics) = renderer.physics_diagnostics() {
+                    info!("{}", diagnostics);
+                }
+                frame_count = 0;
+                frames_start = Instant::now();
+            }
+            if let Some(shader_watcher) = &mut shader_watcher {
+                for (file, source) in shader_watcher.changed() {
+                    renderer.reload_shader(file, &source, &shader_watcher.includes());
+                }
+            }
+            render_or_exit(&mut renderer, &window, frame_count % 2 == 1, control_flow);
+            // The game's score and the latest physics diagnostics
+            let mut new_title = "wroom ten".to_owned();
+            if let Some(game) = &renderer.game {
+                new_title += &format!(" - {}", game);
+            }
+            if let Some(diagnostics) = renderer.physics_diagnostics() {
+                new_title += &format!(" - {}", diagnostics.summary());
+            }
+            if new_title != title {
+                window.set_title(&new_title);
+                title = new_title;
+            }
+        }
+        Event::RedrawEventsCleared => {
+            window.request_redraw();
+        }
+        Event::LoopDestroyed => {
+            renderer.finish_recording();
+            renderer.finish_logs();
+        }
+        _ => {}
+    });
+}
+
+fn main() {
+    // Keeps stdout free for reports
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+    debug!("Starting");
+    let cli = Cli::parse();
+    if cli.min_render_scale > cli.max_render_scale {
+        error!("--min-render-scale can't be above --max-render-scale");
+        std::process::exit(1);
+    }
+    if cli.list_adapters {
+        list_adapters(cli.adapter.backends());
+        return;
+    }
+    let result = match &cli.command {
+        Some(Command::Bench(args)) => bench::run(args, &cli.adapter),
+        Some(Command::Export(args)) => export::run(args, &cli.adapter),
+        Some(Command::Replay(args)) => replay::run(args, &cli.adapter),
+        None => run(&cli).block_on(),
+    };
+    if let Err(err) = result {
+        error!("{}", err);
+        std::process::exit(1);
+    }
 }
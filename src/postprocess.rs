@@ -0,0 +1,438 @@
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+use std::mem;
+
+use crate::tonemap::HDR_FORMAT;
+
+const BLOOM_MIP_COUNT: u32 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PostEffect {
+    Bloom = 1,
+    Vignette = 2,
+    ChromaticAberration = 4,
+    FilmGrain = 8,
+    ColorGrading = 16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PostEffects(u32);
+
+impl PostEffects {
+    pub fn none() -> Self {
+        PostEffects(0)
+    }
+
+    pub fn contains(self, effect: PostEffect) -> bool {
+        self.0 & effect as u32 != 0
+    }
+
+    pub fn toggle(&mut self, effect: PostEffect) -> bool {
+        self.0 ^= effect as u32;
+        self.contains(effect)
+    }
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl Default for PostEffects {
+    fn default() -> Self {
+        PostEffects(PostEffect::Bloom as u32 | PostEffect::Vignette as u32)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+struct PostParams {
+    effects: u32,
+    bloom_threshold: f32,
+    bloom_knee: f32,
+    bloom_intensity: f32,
+    vignette_strength: f32,
+    aberration_strength: f32,
+    _padding: [f32; 2],
+}
+
+pub struct PostProcessor {
+    prefilter_pipeline: wgpu::RenderPipeline,
+    downsample_pipeline: wgpu::RenderPipeline,
+    upsample_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    source_layout: wgpu::BindGroupLayout,
+    composite_layout: wgpu::BindGroupLayout,
+    params_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    bloom_mips: Vec<wgpu::TextureView>,
+    prefilter_bind_group: wgpu::BindGroup,
+    bloom_bind_groups: Vec<wgpu::BindGroup>,
+    composite_bind_group: wgpu::BindGroup,
+    output: wgpu::TextureView,
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    pub vignette_strength: f32,
+    pub aberration_strength: f32,
+}
+
+fn texture_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+impl PostProcessor {
+    pub fn new(
+        device: &wgpu::Device,
+        hdr_view: &wgpu::TextureView,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
+                "postprocess.wgsl"
+            ))),
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: mem::size_of::<PostParams>() as _,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: None,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let params_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(mem::size_of::<PostParams>() as _),
+            },
+            count: None,
+        };
+        let sampler_entry = wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let source_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[params_entry, sampler_entry, texture_entry(2)],
+        });
+        let composite_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                params_entry,
+                sampler_entry,
+                texture_entry(2),
+                texture_entry(3),
+            ],
+        });
+        let create_pipeline = |layout: &wgpu::BindGroupLayout,
+                               entry_point: &str,
+                               blend: Option<wgpu::BlendState>| {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: HDR_FORMAT,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let prefilter_pipeline = create_pipeline(&source_layout, "fs_prefilter", None);
+        let downsample_pipeline = create_pipeline(&source_layout, "fs_downsample", None);
+        let upsample_pipeline = create_pipeline(
+            &source_layout,
+            "fs_upsample",
+            Some(wgpu::BlendState {
+                color: additive,
+                alpha: additive,
+            }),
+        );
+        let composite_pipeline = create_pipeline(&composite_layout, "fs_composite", None);
+        let (bloom_mips, output) = Self::create_textures(device, width, height);
+        let (prefilter_bind_group, bloom_bind_groups, composite_bind_group) =
+            Self::create_bind_groups(
+                device,
+                &source_layout,
+                &composite_layout,
+                &params_buffer,
+                &sampler,
+                hdr_view,
+                &bloom_mips,
+            );
+        PostProcessor {
+            prefilter_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            composite_pipeline,
+            source_layout,
+            composite_layout,
+            params_buffer,
+            sampler,
+            bloom_mips,
+            prefilter_bind_group,
+            bloom_bind_groups,
+            composite_bind_group,
+            output,
+            bloom_threshold: 1.0,
+            bloom_intensity: 0.3,
+            vignette_strength: 1.2,
+            aberration_strength: 0.03,
+        }
+    }
+
+    fn create_textures(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> (Vec<wgpu::TextureView>, wgpu::TextureView) {
+        let bloom_size = wgpu::Extent3d {
+            width: (width / 2).max(1),
+            height: (height / 2).max(1),
+            depth_or_array_layers: 1,
+        };
+        let mip_count = BLOOM_MIP_COUNT.min(bloom_size.max_mips(wgpu::TextureDimension::D2));
+        // A texture per level rather than one with mips, GLES can't sample
+        // from views that start at a non-zero mip level
+        let bloom_mips = (0..mip_count)
+            .map(|mip| {
+                device
+                    .create_texture(&wgpu::TextureDescriptor {
+                        label: None,
+                        size: bloom_size.mip_level_size(mip, wgpu::TextureDimension::D2),
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format: HDR_FORMAT,
+                        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                            | wgpu::TextureUsages::TEXTURE_BINDING,
+                        view_formats: &[],
+                    })
+                    .create_view(&wgpu::TextureViewDescriptor::default())
+            })
+            .collect();
+        let output = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: HDR_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        (bloom_mips, output)
+    }
+
+    fn create_bind_groups(
+        device: &wgpu::Device,
+        source_layout: &wgpu::BindGroupLayout,
+        composite_layout: &wgpu::BindGroupLayout,
+        params_buffer: &wgpu::Buffer,
+        sampler: &wgpu::Sampler,
+        hdr_view: &wgpu::TextureView,
+        bloom_mips: &[wgpu::TextureView],
+    ) -> (wgpu::BindGroup, Vec<wgpu::BindGroup>, wgpu::BindGroup) {
+        let source_bind_group = |source: &wgpu::TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: source_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                ],
+            })
+        };
+        let composite_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: composite_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(hdr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&bloom_mips[0]),
+                },
+            ],
+        });
+        (
+            source_bind_group(hdr_view),
+            bloom_mips.iter().map(source_bind_group).collect(),
+            composite_bind_group,
+        )
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        hdr_view: &wgpu::TextureView,
+        width: u32,
+        height: u32,
+    ) {
+        (self.bloom_mips, self.output) = Self::create_textures(device, width, height);
+        (
+            self.prefilter_bind_group,
+            self.bloom_bind_groups,
+            self.composite_bind_group,
+        ) = Self::create_bind_groups(
+            device,
+            &self.source_layout,
+            &self.composite_layout,
+            &self.params_buffer,
+            &self.sampler,
+            hdr_view,
+            &self.bloom_mips,
+        );
+    }
+
+    pub fn output(&self) -> &wgpu::TextureView {
+        &self.output
+    }
+
+    fn fullscreen_pass(
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        bind_group: &wgpu::BindGroup,
+        target: &wgpu::TextureView,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations { load, store: true },
+            })],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(pipeline);
+        rpass.set_bind_group(0, bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+
+    pub fn render(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        effects: PostEffects,
+    ) {
+        queue.write_buffer(
+            &self.params_buffer,
+            0,
+            bytemuck::cast_slice(&[PostParams {
+                effects: effects.bits(),
+                bloom_threshold: self.bloom_threshold,
+                bloom_knee: self.bloom_threshold * 0.5,
+                bloom_intensity: self.bloom_intensity,
+                vignette_strength: self.vignette_strength,
+                aberration_strength: self.aberration_strength,
+                _padding: [0.0; 2],
+            }]),
+        );
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+        if effects.contains(PostEffect::Bloom) {
+            Self::fullscreen_pass(
+                encoder,
+                &self.prefilter_pipeline,
+                &self.prefilter_bind_group,
+                &self.bloom_mips[0],
+                clear,
+            );
+            for mip in 1..self.bloom_mips.len() {
+                Self::fullscreen_pass(
+                    encoder,
+                    &self.downsample_pipeline,
+                    &self.bloom_bind_groups[mip - 1],
+                    &self.bloom_mips[mip],
+                    clear,
+                );
+            }
+            for mip in (1..self.bloom_mips.len()).rev() {
+                Self::fullscreen_pass(
+                    encoder,
+                    &self.upsample_pipeline,
+                    &self.bloom_bind_groups[mip],
+                    &self.bloom_mips[mip - 1],
+                    wgpu::LoadOp::Load,
+                );
+            }
+        }
+        Self::fullscreen_pass(
+            encoder,
+            &self.composite_pipeline,
+            &self.composite_bind_group,
+            &self.output,
+            clear,
+        );
+    }
+}
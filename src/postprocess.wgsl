@@ -0,0 +1,99 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    // Single triangle covering the whole screen
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    var result: VertexOutput;
+    result.position = vec4<f32>(x, y, 0.0, 1.0);
+    result.uv = vec2<f32>((x + 1.0) / 2.0, (1.0 - y) / 2.0);
+    return result;
+}
+
+struct PostParams {
+    effects: u32,
+    bloom_threshold: f32,
+    bloom_knee: f32,
+    bloom_intensity: f32,
+    vignette_strength: f32,
+    aberration_strength: f32,
+    _padding: vec2<f32>,
+};
+
+// Matches the PostEffect bits on the Rust side
+const EFFECT_BLOOM: u32 = 1u;
+const EFFECT_VIGNETTE: u32 = 2u;
+const EFFECT_CHROMATIC_ABERRATION: u32 = 4u;
+
+@group(0) @binding(0) var<uniform> params: PostParams;
+@group(0) @binding(1) var linear_sampler: sampler;
+@group(0) @binding(2) var source_texture: texture_2d<f32>;
+@group(0) @binding(3) var bloom_texture: texture_2d<f32>;
+
+// Box filter over a 4x4 texel area made of four bilinear taps
+fn sample_box(uv: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source_texture));
+    let d = texel * vec2<f32>(1.0, -1.0);
+    return (textureSample(source_texture, linear_sampler, uv - texel).rgb
+        + textureSample(source_texture, linear_sampler, uv + texel).rgb
+        + textureSample(source_texture, linear_sampler, uv - d).rgb
+        + textureSample(source_texture, linear_sampler, uv + d).rgb) * 0.25;
+}
+
+@fragment
+fn fs_prefilter(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let color = sample_box(vertex.uv);
+    // Soft threshold, quadratic around the knee
+    let brightness = max(max(color.r, color.g), color.b);
+    let soft = clamp(brightness - params.bloom_threshold + params.bloom_knee, 0.0, 2.0 * params.bloom_knee);
+    let soft_weight = soft * soft / (4.0 * params.bloom_knee + 0.00001);
+    let weight = max(soft_weight, brightness - params.bloom_threshold) / max(brightness, 0.00001);
+    return vec4<f32>(color * weight, 1.0);
+}
+
+@fragment
+fn fs_downsample(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(sample_box(vertex.uv), 1.0);
+}
+
+@fragment
+fn fs_upsample(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    // 3x3 tent filter, blended additively into the larger mip
+    let texel = 1.0 / vec2<f32>(textureDimensions(source_texture));
+    var color = textureSample(source_texture, linear_sampler, vertex.uv).rgb * 4.0;
+    color += textureSample(source_texture, linear_sampler, vertex.uv + vec2<f32>(texel.x, 0.0)).rgb * 2.0;
+    color += textureSample(source_texture, linear_sampler, vertex.uv - vec2<f32>(texel.x, 0.0)).rgb * 2.0;
+    color += textureSample(source_texture, linear_sampler, vertex.uv + vec2<f32>(0.0, texel.y)).rgb * 2.0;
+    color += textureSample(source_texture, linear_sampler, vertex.uv - vec2<f32>(0.0, texel.y)).rgb * 2.0;
+    color += textureSample(source_texture, linear_sampler, vertex.uv + texel).rgb;
+    color += textureSample(source_texture, linear_sampler, vertex.uv - texel).rgb;
+    color += textureSample(source_texture, linear_sampler, vertex.uv + texel * vec2<f32>(1.0, -1.0)).rgb;
+    color += textureSample(source_texture, linear_sampler, vertex.uv - texel * vec2<f32>(1.0, -1.0)).rgb;
+    return vec4<f32>(color / 16.0, 1.0);
+}
+
+@fragment
+fn fs_composite(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let from_center = vertex.uv - vec2<f32>(0.5, 0.5);
+    var color: vec3<f32>;
+    if (params.effects & EFFECT_CHROMATIC_ABERRATION) != 0u {
+        let offset = from_center * dot(from_center, from_center) * params.aberration_strength;
+        color.r = textureSample(source_texture, linear_sampler, vertex.uv + offset).r;
+        color.g = textureSample(source_texture, linear_sampler, vertex.uv).g;
+        color.b = textureSample(source_texture, linear_sampler, vertex.uv - offset).b;
+    } else {
+        color = textureSample(source_texture, linear_sampler, vertex.uv).rgb;
+    }
+    if (params.effects & EFFECT_BLOOM) != 0u {
+        color += textureSample(bloom_texture, linear_sampler, vertex.uv).rgb * params.bloom_intensity;
+    }
+    if (params.effects & EFFECT_VIGNETTE) != 0u {
+        let falloff = dot(from_center, from_center) * params.vignette_strength;
+        color *= 1.0 / ((1.0 + falloff) * (1.0 + falloff));
+    }
+    return vec4<f32>(color, 1.0);
+}
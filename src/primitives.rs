@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, mem, path::Path};
+
+use crate::{
+    layout::Primitive,
+    shaders::{PRIMITIVE_BOX, PRIMITIVE_DISK, PRIMITIVE_ORIENTED_BOX, PRIMITIVE_PLANE},
+};
+
+pub const MAX_PRIMITIVES_COUNT: usize = 64;
+// The primitives follow the count at their alignment, as in PrimitiveList
+const ITEMS_OFFSET: usize = 16;
+pub const PRIMITIVES_BUFFER_SIZE: usize =
+    ITEMS_OFFSET + mem::size_of::<Primitive>() * MAX_PRIMITIVES_COUNT;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum Shape {
+    // Infinite and solid on the side the normal points away from
+    Plane {
+        point: [f32; 3],
+        normal: [f32; 3],
+    },
+    Disk {
+        center: [f32; 3],
+        normal: [f32; 3],
+        radius: f32,
+    },
+    Box {
+        center: [f32; 3],
+        half_size: [f32; 3],
+    },
+    // Turned by the angles in degrees around x, then y, then z
+    OrientedBox {
+        center: [f32; 3],
+        half_size: [f32; 3],
+        rotation: [f32; 3],
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScenePrimitive {
+    #[serde(flatten)]
+    pub shape: Shape,
+    // Reflectivity in w, like the spheres
+    pub color: [f32; 4],
+}
+
+// Obstacles, ramps and the like the spheres bounce off, loaded with --scene
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Scene {
+    pub primitives: Vec<ScenePrimitive>,
+}
+
+impl Scene {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|err| format!("Can't read {}: {}", path.display(), err))?;
+        let scene: Scene = serde_json::from_str(&json)
+            .map_err(|err| format!("Can't parse {}: {}", path.display(), err))?;
+        validate(&scene.primitives).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(scene)
+    }
+}
+
+pub fn validate(primitives: &[ScenePrimitive]) -> Result<(), String> {
+    if primitives.len() > MAX_PRIMITIVES_COUNT {
+        return Err(format!(
+            "{} primitives, at most {} are supported",
+            primitives.len(),
+            MAX_PRIMITIVES_COUNT
+        ));
+    }
+    for (index, primitive) in primitives.iter().enumerate() {
+        let valid = match primitive.shape {
+            Shape::Plane { normal, .. } => length(normal) > 0.0,
+            Shape::Disk { normal, radius, .. } => length(normal) > 0.0 && radius > 0.0,
+            Shape::Box { half_size, .. } | Shape::OrientedBox { half_size, .. } => {
+                half_size.iter().all(|&size| size > 0.0)
+            }
+        };
+        if !valid {
+            return Err(format!(
+                "Primitive {} needs a non-zero normal and a positive size",
+                index
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl ScenePrimitive {
+    pub fn to_gpu(&self) -> Primitive {
+        let (pos, kind, half_size, radius, rotation) = match self.shape {
+            Shape::Plane { point, normal } => {
+                (point, PRIMITIVE_PLANE, [0.0; 3], 0.0, facing(normal))
+            }
+            Shape::Disk {
+                center,
+                normal,
+                radius,
+            } => (center, PRIMITIVE_DISK, [0.0; 3], radius, facing(normal)),
+            Shape::Box { center, half_size } => {
+                (center, PRIMITIVE_BOX, half_size, 0.0, [0.0, 0.0, 0.0, 1.0])
+            }
+            Shape::OrientedBox {
+                center,
+                half_size,
+                rotation,
+            } => {
+                let [x, y, z] = rotation.map(|degrees| {
+                    let half = degrees.to_radians() / 2.0;
+                    (half.sin(), half.cos())
+                });
+                let around_x = [x.0, 0.0, 0.0, x.1];
+                let around_y = [0.0, y.0, 0.0, y.1];
+                let around_z = [0.0, 0.0, z.0, z.1];
+                let rotation = multiply(around_z, multiply(around_y, around_x));
+                (center, PRIMITIVE_ORIENTED_BOX, half_size, 0.0, rotation)
+            }
+        };
+        Primitive {
+            pos,
+            kind,
+            half_size,
+            radius,
+            rotation,
+            color: self.color,
+        }
+    }
+}
+
+// The count followed by the primitives, as PrimitiveList in primitives.wgsl
+pub fn buffer_contents(primitives: &[ScenePrimitive]) -> Vec<u8> {
+    let mut contents = vec![0; ITEMS_OFFSET];
+    contents[..4].copy_from_slice(bytemuck::bytes_of(&(primitives.len() as u32)));
+    for primitive in primitives {
+        contents.extend_from_slice(bytemuck::bytes_of(&primitive.to_gpu()));
+    }
+    contents
+}
+
+// The quaternion turning +z to the normal
+fn facing(normal: [f32; 3]) -> [f32; 4] {
+    let length = length(normal);
+    let [x, y, z] = normal.map(|n| n / length);
+    // Any half turn will do, the axis for the others is undefined here
+    if z < -0.9999 {
+        return [1.0, 0.0, 0.0, 0.0];
+    }
+    let half_way = [-y, x, 0.0, 1.0 + z];
+    let length = half_way.iter().map(|q| q * q).sum::<f32>().sqrt();
+    half_way.map(|q| q / length)
+}
+
+// The rotation by b, then by a
+fn multiply(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    [
+        a[3] * b[0] + a[0] * b[3] + a[1] * b[2] - a[2] * b[1],
+        a[3] * b[1] - a[0] * b[2] + a[1] * b[3] + a[2] * b[0],
+        a[3] * b[2] + a[0] * b[1] - a[1] * b[0] + a[2] * b[3],
+        a[3] * b[3] - a[0] * b[0] - a[1] * b[1] - a[2] * b[2],
+    ]
+}
+
+fn length(v: [f32; 3]) -> f32 {
+    v.iter().map(|v| v * v).sum::<f32>().sqrt()
+}
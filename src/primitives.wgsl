@@ -0,0 +1,22 @@
+#include "layout.wgsl"
+
+// The scene's planes, disks and boxes, shared by the physics and the ray tracing
+struct PrimitiveList {
+    count: u32,
+    items: array<Primitive>,
+}
+
+// Rotates v by the unit quaternion q
+fn rotate(q: vec4<f32>, v: vec3<f32>) -> vec3<f32> {
+    let t = 2.0 * cross(q.xyz, v);
+    return v + q.w * t + cross(q.xyz, t);
+}
+
+fn unrotate(q: vec4<f32>, v: vec3<f32>) -> vec3<f32> {
+    return rotate(vec4<f32>(-q.xyz, q.w), v);
+}
+
+// Planes and disks face along this
+fn primitive_normal(primitive: Primitive) -> vec3<f32> {
+    return rotate(primitive.rotation, vec3<f32>(0.0, 0.0, 1.0));
+}
@@ -0,0 +1,196 @@
+use std::{collections::VecDeque, convert::Infallible, mem};
+use tracing::info;
+
+use crate::readback::ReadbackRing;
+
+const HISTORY_LENGTH: usize = 240;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfiledPass {
+    Physics = 0,
+    Diagnostics = 1,
+    Trace = 2,
+    PostProcess = 3,
+    ToneMap = 4,
+}
+
+const PASSES: [ProfiledPass; 5] = [
+    ProfiledPass::Physics,
+    ProfiledPass::Diagnostics,
+    ProfiledPass::Trace,
+    ProfiledPass::PostProcess,
+    ProfiledPass::ToneMap,
+];
+// One at the start of the frame and one after every pass
+const TIMESTAMP_COUNT: u32 = PASSES.len() as u32 + 1;
+
+#[derive(Default)]
+struct RollingStats {
+    samples: VecDeque<f32>,
+}
+
+impl RollingStats {
+    fn push(&mut self, sample: f32) {
+        if self.samples.len() == HISTORY_LENGTH {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    fn mean(&self) -> f32 {
+        self.samples.iter().sum::<f32>() / self.samples.len().max(1) as f32
+    }
+
+    fn min(&self) -> f32 {
+        self.samples.iter().copied().fold(f32::INFINITY, f32::min)
+    }
+
+    fn max(&self) -> f32 {
+        self.samples.iter().copied().fold(0.0, f32::max)
+    }
+}
+
+struct Timestamps {
+    query_set: wgpu::QuerySet,
+    readbacks: ReadbackRing<()>,
+    period: f32,
+    recording: bool,
+}
+
+// Times the passes of a frame with timestamp queries when the adapter has
+// them. Without them there's nothing on the GPU to time passes with, only
+// whole frames from submission to completion.
+pub struct Profiler {
+    timestamps: Option<Timestamps>,
+    stats: [RollingStats; PASSES.len()],
+    submitted_frames: RollingStats,
+}
+
+impl Profiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let timestamps = device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| {
+                Timestamps {
+                    query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                        label: None,
+                        ty: wgpu::QueryType::Timestamp,
+                        count: TIMESTAMP_COUNT,
+                    }),
+                    // Resolved into directly, it only needs to be COPY_DST
+                    readbacks: ReadbackRing::new(
+                        device,
+                        1,
+                        (TIMESTAMP_COUNT as usize * mem::size_of::<u64>()) as _,
+                    ),
+                    period: queue.get_timestamp_period(),
+                    recording: false,
+                }
+            });
+        if timestamps.is_some() {
+            info!("Profiling passes with timestamp queries");
+        } else {
+            info!("No timestamp queries, profiling whole frames from submission to completion");
+        }
+        Profiler {
+            timestamps,
+            stats: Default::default(),
+            submitted_frames: RollingStats::default(),
+        }
+    }
+
+    pub fn is_gpu(&self) -> bool {
+        self.timestamps.is_some()
+    }
+
+    pub fn begin_frame(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if let Some(timestamps) = &mut self.timestamps {
+            // Skipped while the previous results are still being read back
+            if timestamps.readbacks.in_flight() == 0 {
+                encoder.write_timestamp(&timestamps.query_set, 0);
+                timestamps.recording = true;
+            }
+        }
+    }
+
+    pub fn end_pass(&mut self, encoder: &mut wgpu::CommandEncoder, pass: ProfiledPass) {
+        if let Some(timestamps) = &self.timestamps {
+            if timestamps.recording {
+                encoder.write_timestamp(&timestamps.query_set, pass as u32 + 1);
+            }
+        }
+    }
+
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if let Some(timestamps) = &mut self.timestamps {
+            if timestamps.recording {
+                let readback_buffer = timestamps
+                    .readbacks
+                    .next(())
+                    .expect("Can't get the timestamp buffer");
+                encoder.resolve_query_set(
+                    &timestamps.query_set,
+                    0..TIMESTAMP_COUNT,
+                    readback_buffer,
+                    0,
+                );
+                timestamps.recording = false;
+            }
+        }
+    }
+
+    // Has to be called once the encoder with the resolve is submitted
+    pub fn start_readback(&mut self) {
+        if let Some(timestamps) = &mut self.timestamps {
+            timestamps.readbacks.start_readback();
+        }
+    }
+
+    // Returns the whole frame time in seconds when new timestamps arrived
+    pub fn poll(&mut self) -> Option<f32> {
+        let timestamps = self.timestamps.as_mut()?;
+        let period = timestamps.period;
+        let stats = &mut self.stats;
+        let mut frame_time = None;
+        let Ok(()) = timestamps.readbacks.poll(|data, ()| {
+            let ticks: &[u64] = bytemuck::cast_slice(data);
+            for (pass, window) in PASSES.iter().zip(ticks.windows(2)) {
+                let nanoseconds = window[1].wrapping_sub(window[0]) as f32 * period;
+                stats[*pass as usize].push(nanoseconds / 1_000_000.0);
+            }
+            let nanoseconds = ticks[ticks.len() - 1].wrapping_sub(ticks[0]) as f32 * period;
+            frame_time = Some(nanoseconds / 1_000_000_000.0);
+            Ok::<_, Infallible>(())
+        });
+        frame_time
+    }
+
+    // From FrameTimer, in seconds
+    pub fn push_submitted_frame_time(&mut self, frame_time: f32) {
+        self.submitted_frames.push(frame_time * 1000.0);
+    }
+
+    pub fn log(&self) {
+        if !self.is_gpu() {
+            let stats = &self.submitted_frames;
+            info!(
+                "GPU passes unavailable without timestamp queries, whole frames from submission to completion {:.3}ms (min {:.3}ms, max {:.3}ms)",
+                stats.mean(),
+                stats.min(),
+                stats.max()
+            );
+            return;
+        }
+        for pass in PASSES {
+            let stats = &self.stats[pass as usize];
+            info!(
+                "GPU {:?} {:.3}ms (min {:.3}ms, max {:.3}ms)",
+                pass,
+                stats.mean(),
+                stats.min(),
+                stats.max()
+            );
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, OnceLock},
+};
+use tracing::warn;
+
+enum State {
+    Idle,
+    Copied,
+    // Set by the map callback, fails e.g. when the device is lost
+    Mapping(Arc<OnceLock<Result<(), wgpu::BufferAsyncError>>>),
+}
+
+struct Slot<T> {
+    buffer: wgpu::Buffer,
+    state: State,
+    info: Option<T>,
+}
+
+// Readback buffers handed back in the order they were copied along with what
+// the caller recorded about each copy. A single slot keeps one readback in
+// flight, more keep the results of every frame rather than the latest one.
+pub struct ReadbackRing<T> {
+    slots: Vec<Slot<T>>,
+    in_flight: VecDeque<usize>,
+}
+
+impl<T> ReadbackRing<T> {
+    pub fn new(device: &wgpu::Device, slots: usize, size: wgpu::BufferAddress) -> Self {
+        ReadbackRing {
+            slots: (0..slots)
+                .map(|_| Slot {
+                    buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                        label: None,
+                        size,
+                        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    }),
+                    state: State::Idle,
+                    info: None,
+                })
+                .collect(),
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    // The buffer to copy this frame's results into, None when every buffer is
+    // still in flight. Has to be followed by `start_readback` once the encoder
+    // is submitted.
+    pub fn next(&mut self, info: T) -> Option<&wgpu::Buffer> {
+        let index = self
+            .slots
+            .iter()
+            .position(|slot| matches!(slot.state, State::Idle))?;
+        let slot = &mut self.slots[index];
+        slot.state = State::Copied;
+        slot.info = Some(info);
+        self.in_flight.push_back(index);
+        Some(&slot.buffer)
+    }
+
+    pub fn start_readback(&mut self) {
+        for slot in &mut self.slots {
+            if let State::Copied = slot.state {
+                let mapped = Arc::new(OnceLock::new());
+                let callback_mapped = mapped.clone();
+                slot.buffer
+                    .slice(..)
+                    .map_async(wgpu::MapMode::Read, move |result| {
+                        let _ = callback_mapped.set(result);
+                    });
+                slot.state = State::Mapping(mapped);
+            }
+        }
+    }
+
+    // Hands every buffer that has been read back to `read`, oldest first.
+    // Buffers that failed to map are skipped.
+    pub fn poll<E>(&mut self, mut read: impl FnMut(&[u8], T) -> Result<(), E>) -> Result<(), E> {
+        while let Some(&index) = self.in_flight.front() {
+            let slot = &mut self.slots[index];
+            let State::Mapping(mapped) = &slot.state else {
+                break;
+            };
+            let Some(mapped) = mapped.get() else {
+                break;
+            };
+            self.in_flight.pop_front();
+            let info = slot.info.take().expect("Can't get the readback info");
+            let result = match mapped {
+                Ok(()) => {
+                    let result = read(&slot.buffer.slice(..).get_mapped_range(), info);
+                    slot.buffer.unmap();
+                    result
+                }
+                // Never mapped, so there's nothing to unmap
+                Err(err) => {
+                    warn!("Can't map a readback buffer: {}", err);
+                    Ok(())
+                }
+            };
+            slot.state = State::Idle;
+            result?;
+        }
+        Ok(())
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    any::Any,
+    cell::Cell,
+    error::Error,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+use tracing::{error, warn};
+use wgpu_core::device::DeviceError;
+
+// wgpu 0.15 has no device lost callback. The errors it reports for a lost
+// device carry wgpu-core's DeviceError::Lost, which is what marks the device
+// lost here. Submitting and polling a lost device don't report errors though,
+// they panic, so those two calls go through here as well: one that fails
+// before any error said the device is lost counts as a loss if the device
+// can't be polled anymore, any other failure is a bug and stays fatal.
+thread_local! {
+    // While a call that panics on a lost device runs, its panics may be
+    // recovered from rather than take the program down
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+// For panic hooks, the panic being handled may not be fatal
+pub fn is_catching() -> bool {
+    CATCHING.with(Cell::get)
+}
+
+fn catch<R>(f: impl FnOnce() -> R) -> Result<R, Box<dyn Any + Send>> {
+    CATCHING.with(|catching| catching.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|catching| catching.set(false));
+    result
+}
+
+#[derive(Clone)]
+pub struct DeviceLoss {
+    lost: Arc<AtomicBool>,
+    // Other errors are bugs, they're raised on the next poll that works
+    pending: Arc<Mutex<Option<wgpu::Error>>>,
+}
+
+impl DeviceLoss {
+    pub fn watch(device: &wgpu::Device) -> Self {
+        let lost = Arc::new(AtomicBool::new(false));
+        let pending = Arc::new(Mutex::new(None));
+        let (handler_lost, handler_pending) = (lost.clone(), pending.clone());
+        device.on_uncaptured_error(Box::new(move |err| {
+            if handler_lost.load(Ordering::Acquire) {
+                warn!("Ignoring an error of the lost device: {}", err);
+                return;
+            }
+            if reports_loss(&err) {
+                error!("Device lost: {}", err);
+                handler_lost.store(true, Ordering::Release);
+                return;
+            }
+            let mut pending = handler_pending
+                .lock()
+                .expect("Can't lock the device errors");
+            if pending.is_none() {
+                *pending = Some(err);
+            }
+        }));
+        DeviceLoss { lost, pending }
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Acquire)
+    }
+
+    // Returns false when the device is lost
+    pub fn submit(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        command_buffer: wgpu::CommandBuffer,
+    ) -> bool {
+        if self.is_lost() {
+            return false;
+        }
+        let Err(payload) = catch(|| queue.submit(Some(command_buffer))) else {
+            return true;
+        };
+        // Invalid command buffers fail the same way, but leave the device
+        // working. Raised again rather than resumed so the panic hooks see it
+        // as fatal this time.
+        if self.poll(device, wgpu::Maintain::Wait) {
+            let message = payload
+                .downcast_ref::<String>()
+                .map(String::as_str)
+                .or_else(|| payload.downcast_ref::<&str>().copied())
+                .unwrap_or("Queue::submit panicked");
+            panic!("{}", message);
+        }
+        false
+    }
+
+    // Returns false when the device is lost
+    pub fn poll(&self, device: &wgpu::Device, maintain: wgpu::Maintain) -> bool {
+        if self.is_lost() {
+            return false;
+        }
+        let polled = catch(|| device.poll(maintain)).is_ok();
+        let pending = self
+            .pending
+            .lock()
+            .expect("Can't lock the device errors")
+            .take();
+        match (polled, pending) {
+            (true, None) => true,
+            // Same as the default handler
+            (true, Some(err)) => panic!("wgpu error: {}\n", err),
+            (false, pending) => {
+                match pending {
+                    Some(err) => error!("Device lost: {}", err),
+                    None => error!("Device lost"),
+                }
+                self.lost.store(true, Ordering::Release);
+                false
+            }
+        }
+    }
+}
+
+fn reports_loss(err: &wgpu::Error) -> bool {
+    // The errors wrapping a DeviceError are transparent, they don't return it
+    // as their source but print the same
+    let lost = DeviceError::Lost.to_string();
+    let mut source: Option<&(dyn Error + 'static)> = Some(err);
+    while let Some(err) = source {
+        if matches!(err.downcast_ref(), Some(DeviceError::Lost)) || err.to_string() == lost {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
@@ -0,0 +1,342 @@
+use pollster::FutureExt as _;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, panic,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, Once, PoisonError, TryLockError, Weak},
+};
+use tracing::{info, warn};
+
+use crate::{
+    cli::{AdapterArgs, ReplayArgs},
+    error::Error,
+    postprocess::PostEffect,
+    primitives::{self, ScenePrimitive},
+    recovery,
+    state::SimulationState,
+    Renderer, RendererSettings, MAX_BOUNCES, MAX_SAMPLES_PER_PIXEL, MAX_SPHERES_COUNT,
+};
+
+// Bumped whenever an input or a field changes meaning or goes away
+pub const REPLAY_VERSION: u32 = 1;
+
+// Everything from outside the simulation that changes it or the view
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Input {
+    MoveCamera(f32),
+    Resize { width: u32, height: u32 },
+    CycleToneMapOperator,
+    ScaleExposure(f32),
+    TogglePostEffect(PostEffect),
+    ToggleMotionBlur,
+    AdjustShutterAngle(f32),
+    AdjustAperture(f32),
+    // Recorded when the autofocus readback lands rather than on the click,
+    // when that happens depends on the GPU
+    Focus { point: [f32; 2], distance: f32 },
+    CycleSamplesPerPixel,
+    ToggleDynamicResolution,
+    CycleUpscaleFilter,
+    CycleDebugView,
+    LoadState(Box<SimulationState>),
+    ToggleGame,
+    SteerPaddle([f32; 2]),
+    MovePaddle([f32; 2]),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Event {
+    // Index of the tick the input was applied before
+    pub tick: u32,
+    pub input: Input,
+}
+
+// One rendered frame, the simulation steps by the wall clock time since the
+// previous one so that has to be replayed too
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Tick {
+    pub time_step: f32,
+    pub odd_frame: bool,
+}
+
+// How the recorded session started
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplaySettings {
+    pub seed: u64,
+    pub spheres_count: u32,
+    pub samples_per_pixel: u32,
+    pub max_bounces: u32,
+    pub width: u32,
+    pub height: u32,
+    // Whether the session was a game, older recordings never are
+    #[serde(default)]
+    pub game: bool,
+    #[serde(default)]
+    pub primitives: Vec<ScenePrimitive>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub settings: ReplaySettings,
+    pub events: Vec<Event>,
+    pub ticks: Vec<Tick>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|err| format!("Can't read {}: {}", path.display(), err))?;
+        let replay: Replay = serde_json::from_str(&json)
+            .map_err(|err| format!("Can't parse {}: {}", path.display(), err))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(format!(
+                "{} is version {}, only version {} is supported",
+                path.display(),
+                replay.version,
+                REPLAY_VERSION
+            ));
+        }
+        if replay
+            .events
+            .windows(2)
+            .any(|pair| pair[0].tick > pair[1].tick)
+        {
+            return Err(format!("{} has events out of order", path.display()));
+        }
+        replay
+            .validate()
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(replay)
+    }
+
+    // The same limits the command line and saved states have
+    fn validate(&self) -> Result<(), String> {
+        let settings = &self.settings;
+        if !(1..=MAX_SPHERES_COUNT).contains(&(settings.spheres_count as usize)) {
+            return Err(format!(
+                "{} spheres, expected between 1 and {}",
+                settings.spheres_count, MAX_SPHERES_COUNT
+            ));
+        }
+        if !(1..=MAX_SAMPLES_PER_PIXEL).contains(&settings.samples_per_pixel)
+            || !(1..=MAX_BOUNCES).contains(&settings.max_bounces)
+        {
+            return Err(format!(
+                "{} samples per pixel and {} bounces, expected 1 to {} and 1 to {}",
+                settings.samples_per_pixel,
+                settings.max_bounces,
+                MAX_SAMPLES_PER_PIXEL,
+                MAX_BOUNCES
+            ));
+        }
+        if settings.width == 0 || settings.height == 0 {
+            return Err(format!(
+                "Recorded at {}x{}, which can't be rendered",
+                settings.width, settings.height
+            ));
+        }
+        primitives::validate(&settings.primitives)?;
+        for event in &self.events {
+            if let Input::LoadState(state) = &event.input {
+                state
+                    .validate()
+                    .map_err(|err| format!("Tick {}: {}", event.tick, err))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string(self).expect("Can't serialize the replay");
+        fs::write(path, json).map_err(|err| format!("Can't write {}: {}", path.display(), err))
+    }
+}
+
+// The latest recording, for the panic hook. Gone once its Recorder is.
+type Recording = (PathBuf, Weak<Mutex<Replay>>);
+static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+
+// Installed by the first recording and kept for the ones after it, so hooks
+// don't pile up. Runs the hook that was there before first.
+fn save_on_panic() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        // Likely a lost device that's about to be recovered from
+        if recovery::is_catching() {
+            return;
+        }
+        let recording = match RECORDING.try_lock() {
+            Ok(recording) => recording.clone(),
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner().clone(),
+            Err(TryLockError::WouldBlock) => return,
+        };
+        // Gone once the recording is finished
+        let Some((path, Some(replay))) = recording.map(|(path, replay)| (path, replay.upgrade()))
+        else {
+            return;
+        };
+        let replay = match replay.try_lock() {
+            Ok(replay) => replay,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            // Panicked while recording, further up this thread
+            Err(TryLockError::WouldBlock) => return,
+        };
+        match replay.save(&path) {
+            Ok(()) => eprintln!(
+                "Saved {} recorded ticks to {}",
+                replay.ticks.len(),
+                path.display()
+            ),
+            Err(err) => eprintln!("{}", err),
+        }
+    }));
+}
+
+// Collects the inputs and ticks of a session, written out when it ends or
+// when the program panics before that
+pub struct Recorder {
+    path: PathBuf,
+    replay: Arc<Mutex<Replay>>,
+}
+
+impl Recorder {
+    pub fn new(path: PathBuf, settings: ReplaySettings) -> Self {
+        let replay = Arc::new(Mutex::new(Replay {
+            version: REPLAY_VERSION,
+            settings,
+            events: Vec::new(),
+            ticks: Vec::new(),
+        }));
+        static SAVE_ON_PANIC: Once = Once::new();
+        SAVE_ON_PANIC.call_once(save_on_panic);
+        *RECORDING.lock().unwrap_or_else(PoisonError::into_inner) =
+            Some((path.clone(), Arc::downgrade(&replay)));
+        Recorder { path, replay }
+    }
+
+    fn replay(&self) -> MutexGuard<'_, Replay> {
+        self.replay.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Applies to the next tick
+    pub fn record(&mut self, input: Input) {
+        let mut replay = self.replay();
+        let tick = replay.ticks.len() as u32;
+        replay.events.push(Event { tick, input });
+    }
+
+    pub fn tick(&mut self, time_step: f32, odd_frame: bool) {
+        self.replay().ticks.push(Tick {
+            time_step,
+            odd_frame,
+        });
+    }
+
+    pub fn finish(self) -> Result<(PathBuf, u32), String> {
+        let replay = self.replay();
+        replay.save(&self.path)?;
+        let ticks = replay.ticks.len() as u32;
+        drop(replay);
+        Ok((self.path, ticks))
+    }
+}
+
+// Renders a tick and writes its frame when frames are asked for
+fn replay_tick(
+    renderer: &mut Renderer,
+    tick: &Tick,
+    index: usize,
+    frames: Option<&Path>,
+    metadata: &[(String, String)],
+) -> Result<(), String> {
+    renderer
+        .render_and_wait(tick.odd_frame)
+        .map_err(|err| format!("Can't render tick {}: {}", index, err))?;
+    if let Some(frames) = frames {
+        let path = frames.join(format!("frame-{:05}.png", index));
+        let image = renderer
+            .captured_frame
+            .take()
+            .ok_or_else(|| format!("Can't read back tick {}", index))?;
+        image.write_png(&path, metadata)?;
+    }
+    Ok(())
+}
+
+pub fn run(args: &ReplayArgs, adapter: &AdapterArgs) -> Result<(), Error> {
+    let replay = Replay::load(&args.input).map_err(Error::Replay)?;
+    let settings = RendererSettings {
+        spheres_count: replay.settings.spheres_count,
+        seed: Some(replay.settings.seed),
+        samples_per_pixel: replay.settings.samples_per_pixel,
+        max_bounces: replay.settings.max_bounces,
+        primitives: replay.settings.primitives.clone(),
+        ..adapter.renderer_settings()
+    };
+    let mut renderer =
+        Renderer::new_headless(replay.settings.width, replay.settings.height, &settings)
+            .block_on()?;
+    // The render scale follows the GPU's frame times, which a replay can't
+    // reproduce
+    renderer.resolution_scaler.enabled = false;
+    renderer.resize_render_targets();
+    if replay.settings.game {
+        renderer.start_game();
+    }
+    if let Some(frames) = &args.frames {
+        fs::create_dir_all(frames)
+            .map_err(|err| Error::Replay(format!("Can't create {}: {}", frames.display(), err)))?;
+    }
+    if let Some(path) = &args.diagnostics {
+        renderer
+            .start_diagnostics_log(path)
+            .map_err(|err| Error::Diagnostics(path.clone(), err))?;
+    }
+    if let Some(directory) = &args.analysis {
+        renderer
+            .start_analysis(directory)
+            .map_err(|err| Error::Analysis(directory.clone(), err))?;
+    }
+    let mut events = replay.events.into_iter().peekable();
+    for (index, tick) in replay.ticks.iter().enumerate() {
+        while let Some(event) = events.next_if(|event| event.tick as usize == index) {
+            match event.input {
+                Input::ToggleDynamicResolution => {
+                    warn!("Skipping the dynamic resolution toggle on tick {}", index)
+                }
+                input => renderer.apply(input),
+            }
+        }
+        renderer.time_step = Some(tick.time_step);
+        let last = index + 1 == replay.ticks.len();
+        if let (true, Some(path)) = (last, &args.save_state) {
+            renderer.save_state(path.clone());
+        }
+        let metadata = renderer.metadata();
+        if args.frames.is_some() {
+            renderer.capture_frame();
+        }
+        if let Err(err) = replay_tick(
+            &mut renderer,
+            tick,
+            index,
+            args.frames.as_deref(),
+            &metadata,
+        ) {
+            renderer.finish_logs();
+            return Err(Error::Replay(err));
+        }
+    }
+    renderer.finish_logs();
+    if let Some(diagnostics) = renderer.physics_diagnostics() {
+        info!("{}", diagnostics);
+    }
+    info!(
+        "Replayed {} ticks from {}",
+        replay.ticks.len(),
+        args.input.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::recovery::DeviceLoss;
+
+// Waits this many frames after a change so the measurements settle on the
+// new resolution before deciding again
+const SETTLE_FRAMES: u32 = 30;
+const SCALE_STEP: f32 = 0.05;
+const SMOOTHING: f32 = 0.1;
+
+// Picks the internal render scale from the measured GPU frame time
+pub struct ResolutionScaler {
+    pub enabled: bool,
+    pub target_fps: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    scale: f32,
+    frame_time: Option<f32>,
+    settle_frames: u32,
+}
+
+impl ResolutionScaler {
+    pub fn new(target_fps: f32, min_scale: f32, max_scale: f32) -> Self {
+        ResolutionScaler {
+            enabled: true,
+            target_fps,
+            min_scale,
+            max_scale,
+            scale: max_scale,
+            frame_time: None,
+            settle_frames: SETTLE_FRAMES,
+        }
+    }
+
+    pub fn scale(&self) -> f32 {
+        if self.enabled {
+            self.scale
+        } else {
+            1.0
+        }
+    }
+
+    pub fn scaled_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scale = self.scale();
+        (
+            ((width as f32 * scale).round() as u32).max(1),
+            ((height as f32 * scale).round() as u32).max(1),
+        )
+    }
+
+    pub fn toggle(&mut self) -> bool {
+        self.enabled = !self.enabled;
+        self.reset();
+        self.enabled
+    }
+
+    // Returns true when the scale changed and the render targets have to be
+    // recreated
+    pub fn update(&mut self, gpu_frame_time: f32) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let frame_time = match self.frame_time {
+            Some(frame_time) => frame_time + (gpu_frame_time - frame_time) * SMOOTHING,
+            None => gpu_frame_time,
+        };
+        self.frame_time = Some(frame_time);
+        if self.settle_frames > 0 {
+            self.settle_frames -= 1;
+            return false;
+        }
+        // The cost is roughly proportional to the pixel count, so the square
+        // of the scale
+        let ideal = self.scale * (1.0 / self.target_fps / frame_time.max(1e-6)).sqrt();
+        let scale =
+            ((ideal / SCALE_STEP).round() * SCALE_STEP).clamp(self.min_scale, self.max_scale);
+        if (scale - self.scale).abs() < SCALE_STEP * 0.5 {
+            return false;
+        }
+        self.scale = scale;
+        self.reset();
+        true
+    }
+
+    fn reset(&mut self) {
+        self.frame_time = None;
+        self.settle_frames = SETTLE_FRAMES;
+    }
+}
+
+// Measures from submission until the queue reports the work as done, which
+// is close to the GPU time when the GPU is the bottleneck
+pub struct FrameTimer {
+    last: Arc<Mutex<Option<f32>>>,
+}
+
+impl FrameTimer {
+    pub fn new() -> Self {
+        FrameTimer {
+            last: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    // Starts timing before the submission, some backends do the work in it.
+    // Returns false when the device is lost.
+    pub fn submit(
+        &self,
+        device_loss: &DeviceLoss,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        command_buffer: wgpu::CommandBuffer,
+    ) -> bool {
+        let submitted = Instant::now();
+        if !device_loss.submit(device, queue, command_buffer) {
+            return false;
+        }
+        let last = self.last.clone();
+        queue.on_submitted_work_done(move || {
+            *last.lock().expect("Can't lock the frame timer") =
+                Some(submitted.elapsed().as_secs_f32());
+        });
+        true
+    }
+
+    pub fn poll(&self) -> Option<f32> {
+        self.last.lock().expect("Can't lock the frame timer").take()
+    }
+}
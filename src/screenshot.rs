@@ -0,0 +1,211 @@
+use std::{
+    convert::Infallible,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::readback::ReadbackRing;
+
+const BYTES_PER_PIXEL: u32 = 4;
+
+struct Capture {
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    bgra: bool,
+}
+
+// Tightly packed 8 bit RGBA
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn write_png(&self, path: &Path, metadata: &[(String, String)]) -> Result<(), String> {
+        write_png(self, path, metadata)
+            .map_err(|err| format!("Can't write {}: {}", path.display(), err))
+    }
+}
+
+// Copies a rendered frame into a buffer and hands it back once the buffer is
+// mapped, a few frames later.
+pub struct ScreenCapture {
+    // Recreated when the frame size changes
+    readbacks: Option<(wgpu::BufferAddress, ReadbackRing<Capture>)>,
+    requested: bool,
+}
+
+impl ScreenCapture {
+    pub fn new() -> Self {
+        ScreenCapture {
+            readbacks: None,
+            requested: false,
+        }
+    }
+
+    // Returns false if the previous capture is still in flight
+    pub fn request(&mut self) -> bool {
+        let in_flight = self
+            .readbacks
+            .as_ref()
+            .is_some_and(|(_, readbacks)| readbacks.in_flight() > 0);
+        if self.requested || in_flight {
+            return false;
+        }
+        self.requested = true;
+        true
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested
+    }
+
+    // The texture needs COPY_SRC and an 8 bit RGBA or BGRA format, has to be
+    // followed by `start_readback` once the encoder is submitted. Other formats
+    // drop the request.
+    pub fn copy(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Result<(), String> {
+        if !self.requested {
+            return Ok(());
+        }
+        let bgra = match format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            _ => {
+                self.requested = false;
+                return Err(format!("Can't capture {:?} frames", format));
+            }
+        };
+        // Rows of a texture copy have to be aligned
+        let padded_bytes_per_row = (width * BYTES_PER_PIXEL)
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let size = (padded_bytes_per_row * height) as wgpu::BufferAddress;
+        if self.readbacks.as_ref().map(|(size, _)| *size) != Some(size) {
+            self.readbacks = Some((size, ReadbackRing::new(device, 1, size)));
+        }
+        let (_, readbacks) = self
+            .readbacks
+            .as_mut()
+            .expect("Can't get the capture buffer");
+        let buffer = readbacks
+            .next(Capture {
+                width,
+                height,
+                padded_bytes_per_row,
+                bgra,
+            })
+            .expect("Can't get the capture buffer");
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.requested = false;
+        Ok(())
+    }
+
+    pub fn start_readback(&mut self) {
+        if let Some((_, readbacks)) = &mut self.readbacks {
+            readbacks.start_readback();
+        }
+    }
+
+    pub fn poll(&mut self) -> Option<Image> {
+        let (_, readbacks) = self.readbacks.as_mut()?;
+        let mut image = None;
+        let Ok(()) = readbacks.poll(|data, capture| {
+            let mut pixels =
+                Vec::with_capacity((capture.width * capture.height * BYTES_PER_PIXEL) as _);
+            for row in data.chunks(capture.padded_bytes_per_row as _) {
+                pixels.extend_from_slice(&row[..(capture.width * BYTES_PER_PIXEL) as usize]);
+            }
+            if capture.bgra {
+                for pixel in pixels.chunks_mut(BYTES_PER_PIXEL as _) {
+                    pixel.swap(0, 2);
+                }
+            }
+            image = Some(Image {
+                width: capture.width,
+                height: capture.height,
+                pixels,
+            });
+            Ok::<_, Infallible>(())
+        });
+        image
+    }
+}
+
+fn write_png(
+    image: &Image,
+    path: &Path,
+    metadata: &[(String, String)],
+) -> Result<(), png::EncodingError> {
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    // Both the sRGB formats and the shader side encoding end up in sRGB
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    for (keyword, text) in metadata {
+        encoder.add_text_chunk(keyword.clone(), text.clone())?;
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.pixels)?;
+    writer.finish()
+}
+
+// wroom-ten-YYYYMMDD-HHMMSS.extension in UTC, in the given directory
+pub fn timestamped_path(directory: &Path, extension: &str) -> PathBuf {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+    // Days to the civil date, from Howard Hinnant's chrono-compatible algorithms
+    let days = days as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    directory.join(format!(
+        "wroom-ten-{:04}{:02}{:02}-{:02}{:02}{:02}.{}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        extension
+    ))
+}
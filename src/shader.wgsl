@@ -1,79 +1,112 @@
-struct VertexOutput {
-    @builtin(position) position: vec4<f32>,
-    @location(0) tex_coords: vec2<f32>,
-}
+#include "layout.wgsl"
+#include "primitives.wgsl"
 
-@vertex
-fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
-    var result: VertexOutput;
-    var x: f32;
-    var y: f32;
-    switch (in_vertex_index) {
-        case 0u: {
-            x = -1.0;
-            y = 1.0;
-        }
-        case 1u,4u: {
-            x = 1.0;
-            y = 1.0;
-        }
-        case 2u,3u: {
-            x = -1.0;
-            y = -1.0;
-        }
-        case 5u: {
-            x = 1.0;
-            y = -1.0;
-        }
-        default: {
-            break;
+// One invocation per pixel of a TILE_SIZE x TILE_SIZE tile, both come from the
+// Rust side
+
+// Each tile loads the spheres in chunks into workgroup memory, so every
+// invocation in a tile has to run the same number of sphere searches.
+var<workgroup> tile_spheres: array<Sphere, TILE_PIXELS>;
+
+// Samples are stratified over a grid within the pixel and over the shutter
+// interval and the lens, jittered per pixel and frame to trade banding for noise.
+@compute
+@workgroup_size(TILE_SIZE, TILE_SIZE)
+fn cs_trace(
+    @builtin(global_invocation_id) global_invocation_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    let size = vec2<u32>(textureDimensions(output_texture));
+    let pixel = vec2<f32>(global_invocation_id.xy);
+    var color = vec3<f32>(0.0, 0.0, 0.0);
+    if params.debug_view != DEBUG_VIEW_OFF {
+        color = debug_view_for_point((pixel + 0.5) / vec2<f32>(size), local_index);
+    } else {
+        let seed = pixel + f32(params.frame_index % 4096u);
+        let samples = params.samples_per_pixel;
+        // Counts that aren't squares leave the last row short, every sample
+        // still lands in the pixel
+        let columns = u32(ceil(sqrt(f32(samples))));
+        let rows = (samples + columns - 1u) / columns;
+        for (var sample = 0u; sample < samples; sample++) {
+            let stratum = f32(sample);
+            var offset = vec2<f32>(0.5, 0.5);
+            if samples > 1u {
+                let cell = vec2<f32>(f32(sample % columns), f32(sample / columns));
+                offset = (cell + vec2<f32>(hash(seed + stratum), hash(seed.yx + stratum))) / vec2<f32>(f32(columns), f32(rows));
+            }
+            let t = -params.shutter_time * (stratum + hash(seed + 0.25)) / f32(samples);
+            let angle = 2.0 * PI * (stratum + hash(seed.yx + 0.75)) / f32(samples);
+            let radius = params.aperture * sqrt(hash(seed + vec2<f32>(stratum, 0.5)));
+            let lens = radius * vec2<f32>(cos(angle), sin(angle));
+            let ray = make_start_ray_for_point((pixel + offset) / vec2<f32>(size), lens);
+            color += cast_ray(ray, t, local_index);
         }
+        color /= f32(samples);
+    }
+    if global_invocation_id.x < size.x && global_invocation_id.y < size.y {
+        textureStore(output_texture, vec2<i32>(global_invocation_id.xy), vec4<f32>(color, 1.0));
     }
-    result.position = vec4<f32>(x, y, 0.0, 1.0);
-    result.tex_coords = vec2<f32>((x + 1.0) / 2.0, (1.0 - y) / 2.0);
-    return result;
 }
 
-@fragment
-fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(trace_for_point(vertex.tex_coords.xy), 1.0);
+// Finds what's under the clicked point to put the focus plane there
+@compute
+@workgroup_size(1)
+fn cs_autofocus() {
+    let ray = make_start_ray_for_point(params.focus_point, vec2<f32>(0.0, 0.0));
+    let hit = find_hit(ray, 0.0);
+    let poi = ray.origin + ray.dir * min(hit.toi, MAX_TOI);
+    autofocus_distance = poi.z - params.camera_pos.z;
 }
 
 // Ray-tracing starts here
-struct RayTracingParams {
-    camera_pos: vec4<f32>,
-    aspect_ratio: f32,
-    spheres_count: u32,
-    _padding: vec2<f32>,
-};
-
-struct Sphere {
-    pos: vec3<f32>,
-    r: f32,
-    _vel: vec4<f32>,
-    color: vec4<f32>,
-}
-
 @group(0) @binding(0) var<uniform> params: RayTracingParams;
 @group(0) @binding(1) var<storage, read> spheres: array<Sphere>;
+@group(0) @binding(2) var<storage, read> primitives: PrimitiveList;
+@group(1) @binding(0) var output_texture: texture_storage_2d<rgba16float, write>;
+@group(1) @binding(1) var<storage, read_write> autofocus_distance: f32;
 
 const CAMERA_X_AXIS: vec3<f32> = vec3<f32>(1.0, 0.0, 0.0);
 const CAMERA_Y_AXIS: vec3<f32> = vec3<f32>(0.0, -1.0, 0.0);
 const F32_MAX: f32 = 3.40282347E+38;
 const EPSILON: f32 = 0.0001;
-const REFLECTIONS_N: i32 = 5;
 const MAX_TOI: f32 = 100000.0;
+const PI: f32 = 3.14159265;
+const NO_SPHERE: u32 = 0xffffffffu;
+const MAX_DEBUG_VELOCITY: f32 = 1.0;
+const DEBUG_VIEW_OFF: u32 = 0u;
+// Paddle colors with the reflectivity in w
+const PLAYER_PADDLE_COLOR: vec4<f32> = vec4<f32>(0.1, 0.3, 0.8, 0.5);
+const OPPONENT_PADDLE_COLOR: vec4<f32> = vec4<f32>(0.8, 0.2, 0.1, 0.5);
+const PADDLE_FRAME: f32 = 0.08;
+
+// Statistics of the last cast_ray call, only looked at by the debug views
+var<private> bounce_count: u32;
+var<private> sphere_test_count: u32;
+
+fn hash(p: vec2<f32>) -> f32 {
+    let q = fract(vec3<f32>(p.xyx) * 0.1031);
+    let r = q + dot(q, q.yzx + 33.33);
+    return fract((r.x + r.y) * r.z);
+}
 
 struct Ray {
     origin: vec3<f32>,
     dir: vec3<f32>,
 }
 
-fn make_start_ray_for_point(coord: vec2<f32>) -> Ray {
+// Thin lens camera, rays through every point of the lens meet the pinhole ray
+// on the focus plane. With the lens point at the center it's a pinhole camera.
+fn make_start_ray_for_point(coord: vec2<f32>, lens: vec2<f32>) -> Ray {
     var ray: Ray;
-    ray.origin = params.camera_pos.xyz;
-    let dir_point = (coord.x - 0.5) * CAMERA_X_AXIS + (coord.y - 0.5) * CAMERA_Y_AXIS / params.aspect_ratio;
-    ray.dir = dir_point - params.camera_pos.xyz;
+    // The screen is centered on the room's axis a unit in front of the camera
+    let screen_center = vec3<f32>(0.0, 0.0, params.camera_pos.z + 1.0);
+    let dir_point = screen_center + (coord.x - 0.5) * CAMERA_X_AXIS + (coord.y - 0.5) * CAMERA_Y_AXIS / params.aspect_ratio;
+    let pinhole_dir = dir_point - params.camera_pos.xyz;
+    let focus_scale = params.focus_distance / pinhole_dir.z;
+    let focus_point = params.camera_pos.xyz + pinhole_dir * focus_scale;
+    ray.origin = params.camera_pos.xyz + lens.x * CAMERA_X_AXIS + lens.y * CAMERA_Y_AXIS;
+    ray.dir = (focus_point - ray.origin) / focus_scale;
     return ray;
 }
 
@@ -99,108 +132,309 @@ fn intersect_sphere(ray: Ray, pos: vec3<f32>, r: f32) -> f32 {
     return min(t1, t2);
 }
 
-fn cast_ray(in_ray: Ray) -> vec3<f32> {
-    var coef_color = vec3<f32>(1.0, 1.0, 1.0);
-    var offset_color = vec3<f32>(0.0, 0.0, 0.0);
-    var ray = in_ray;
-    for (var i: i32 = 0; i < REFLECTIONS_N; i++) {
-        var min_toi: f32 = MAX_TOI;
-        var color = vec3<f32>(1.0, 0.0, 0.0);
-        var normal = vec3<f32>(0.0, 0.0, 0.0);
-        var refl = 0.0;
-        var with_sphere = false;
-        if abs(ray.dir.x) > EPSILON {
-            if ray.dir.x < 0.0 {
-                let toi = (-4.0 - ray.origin.x) / ray.dir.x;
-                if toi < min_toi {
-                    min_toi = toi;
-                    color = vec3<f32>(0.5, 0.0, 0.5);
-                    normal = vec3<f32>(1.0, 0.0, 0.0);
-                }
-            } else {
-                let toi = (4.0 - ray.origin.x) / ray.dir.x;
-                if toi < min_toi {
-                    min_toi = toi;
-                    color = vec3<f32>(0.5, 0.0, 0.0);
-                    normal = vec3<f32>(-1.0, 0.0, 0.0);
-                }
+// Distance to where the ray enters an axis-aligned box in w, F32_MAX if it
+// doesn't, and the normal of the face it enters through in xyz
+fn intersect_box(ray: Ray, center: vec3<f32>, half_size: vec3<f32>) -> vec4<f32> {
+    // Rays parallel to a face would divide by zero
+    let dir = select(ray.dir, vec3<f32>(EPSILON), abs(ray.dir) < vec3<f32>(EPSILON));
+    let t0 = (center - half_size - ray.origin) / dir;
+    let t1 = (center + half_size - ray.origin) / dir;
+    let near = min(t0, t1);
+    let far = max(t0, t1);
+    let enter = max(max(near.x, near.y), near.z);
+    let exit = min(min(far.x, far.y), far.z);
+    if enter > exit || enter < EPSILON {
+        return vec4<f32>(0.0, 0.0, 0.0, F32_MAX);
+    }
+    var normal = vec3<f32>(0.0, 0.0, -sign(dir.z));
+    if enter == near.x {
+        normal = vec3<f32>(-sign(dir.x), 0.0, 0.0);
+    } else if enter == near.y {
+        normal = vec3<f32>(0.0, -sign(dir.y), 0.0);
+    }
+    return vec4<f32>(normal, enter);
+}
+
+// Same for any primitive, with the normal facing the ray
+fn intersect_primitive(ray: Ray, primitive: Primitive) -> vec4<f32> {
+    let miss = vec4<f32>(0.0, 0.0, 0.0, F32_MAX);
+    switch primitive.kind {
+        case PRIMITIVE_BOX: {
+            return intersect_box(ray, primitive.pos, primitive.half_size);
+        }
+        case PRIMITIVE_ORIENTED_BOX: {
+            let local_ray = Ray(
+                unrotate(primitive.rotation, ray.origin - primitive.pos),
+                unrotate(primitive.rotation, ray.dir),
+            );
+            let box_hit = intersect_box(local_ray, vec3<f32>(0.0), primitive.half_size);
+            return vec4<f32>(rotate(primitive.rotation, box_hit.xyz), box_hit.w);
+        }
+        default: {
+            let normal = primitive_normal(primitive);
+            let facing = dot(ray.dir, normal);
+            if abs(facing) < EPSILON {
+                return miss;
+            }
+            let toi = dot(primitive.pos - ray.origin, normal) / facing;
+            if toi < EPSILON {
+                return miss;
             }
+            let poi = ray.origin + ray.dir * toi;
+            if primitive.kind == PRIMITIVE_DISK && length(poi - primitive.pos) > primitive.radius {
+                return miss;
+            }
+            return vec4<f32>(normal * -sign(facing), toi);
         }
-        if abs(ray.dir.y) > EPSILON {
-            if ray.dir.y < 0.0 {
-                let toi = (-2.0 - ray.origin.y) / ray.dir.y;
-                if toi < min_toi {
-                    min_toi = toi;
-                    color = vec3<f32>(0.0, 0.5, 0.5);
-                    normal = vec3<f32>(0.0, 1.0, 0.0);
-                }
-            } else {
-                let toi = (2.0 - ray.origin.y) / ray.dir.y;
-                if toi < min_toi {
-                    min_toi = toi;
-                    color = vec3<f32>(0.5, 0.5, 0.0);
-                    normal = vec3<f32>(0.0, -1.0, 0.0);
-                }
+    }
+}
+
+struct Hit {
+    toi: f32,
+    color: vec3<f32>,
+    normal: vec3<f32>,
+    refl: f32,
+    with_sphere: bool,
+    sphere: u32,
+}
+
+fn find_wall_hit(ray: Ray) -> Hit {
+    var min_toi: f32 = MAX_TOI;
+    var color = vec3<f32>(1.0, 0.0, 0.0);
+    var normal = vec3<f32>(0.0, 0.0, 0.0);
+    if abs(ray.dir.x) > EPSILON {
+        if ray.dir.x < 0.0 {
+            let toi = (ROOM_MIN.x - ray.origin.x) / ray.dir.x;
+            if toi < min_toi {
+                min_toi = toi;
+                color = vec3<f32>(0.5, 0.0, 0.5);
+                normal = vec3<f32>(1.0, 0.0, 0.0);
+            }
+        } else {
+            let toi = (ROOM_MAX.x - ray.origin.x) / ray.dir.x;
+            if toi < min_toi {
+                min_toi = toi;
+                color = vec3<f32>(0.5, 0.0, 0.0);
+                normal = vec3<f32>(-1.0, 0.0, 0.0);
             }
         }
-        if abs(ray.dir.z) > EPSILON {
-            if ray.dir.z < 0.0 {
-                let toi = (-0.0 - ray.origin.z) / ray.dir.z;
-                if toi < min_toi {
-                    min_toi = toi;
-                    color = vec3<f32>(0.0, 0.5, 0.0);
-                    normal = vec3<f32>(0.0, 0.0, 1.0);
-                }
-            } else {
-                let toi = (16.0 - ray.origin.z) / ray.dir.z;
-                if toi < min_toi {
-                    min_toi = toi;
-                    color = vec3<f32>(0.0, 0.0, 0.5);
-                    normal = vec3<f32>(0.0, 0.0, -1.0);
-                }
+    }
+    if abs(ray.dir.y) > EPSILON {
+        if ray.dir.y < 0.0 {
+            let toi = (ROOM_MIN.y - ray.origin.y) / ray.dir.y;
+            if toi < min_toi {
+                min_toi = toi;
+                color = vec3<f32>(0.0, 0.5, 0.5);
+                normal = vec3<f32>(0.0, 1.0, 0.0);
+            }
+        } else {
+            let toi = (ROOM_MAX.y - ray.origin.y) / ray.dir.y;
+            if toi < min_toi {
+                min_toi = toi;
+                color = vec3<f32>(0.5, 0.5, 0.0);
+                normal = vec3<f32>(0.0, -1.0, 0.0);
             }
         }
-
-        for (var sphere: u32 = 0u; sphere < params.spheres_count; sphere++) {
-            let toi = intersect_sphere(ray, spheres[sphere].pos, spheres[sphere].r);
-            if toi > EPSILON && toi < min_toi {
+    }
+    if abs(ray.dir.z) > EPSILON {
+        if ray.dir.z < 0.0 {
+            let toi = (ROOM_MIN.z - ray.origin.z) / ray.dir.z;
+            if toi < min_toi {
                 min_toi = toi;
-                let poi: vec3<f32> = ray.origin + ray.dir * toi;
-                normal = normalize(poi - spheres[sphere].pos);
-                color = spheres[sphere].color.xyz;
-                refl = spheres[sphere].color[3];
-                with_sphere = true;
+                color = vec3<f32>(0.0, 0.5, 0.0);
+                normal = vec3<f32>(0.0, 0.0, 1.0);
+            }
+        } else {
+            let toi = (ROOM_MAX.z - ray.origin.z) / ray.dir.z;
+            if toi < min_toi {
+                min_toi = toi;
+                color = vec3<f32>(0.0, 0.0, 0.5);
+                normal = vec3<f32>(0.0, 0.0, -1.0);
             }
         }
+    }
+    return Hit(min_toi, color, normal, 0.0, false, NO_SPHERE);
+}
+
+// Spheres behind the ray or further away than the closest hit so far are
+// skipped without intersecting them, the rest count as sphere tests
+fn closer_sphere_hit(ray: Ray, t: f32, sphere: Sphere, index: u32, hit: Hit) -> Hit {
+    let pos = sphere.pos + sphere.vel.xyz * t;
+    let to_center = pos - ray.origin;
+    let dir_length = length(ray.dir);
+    let reach = min(hit.toi, MAX_TOI) * dir_length + sphere.r;
+    if dot(to_center, ray.dir) < -sphere.r * dir_length || dot(to_center, to_center) > reach * reach {
+        return hit;
+    }
+    sphere_test_count += 1u;
+    let toi = intersect_sphere(ray, pos, sphere.r);
+    if toi > EPSILON && toi < hit.toi {
+        let poi: vec3<f32> = ray.origin + ray.dir * toi;
+        return Hit(toi, sphere.color.xyz, normalize(poi - pos), sphere.color[3], true, index);
+    }
+    return hit;
+}
 
-        if min_toi < MAX_TOI {
-            let poi: vec3<f32> = ray.origin + ray.dir * min_toi;
-            if !with_sphere {
+// The player looks through their own paddle, only its frame is solid
+fn closer_paddle_hit(ray: Ray, paddle: vec4<f32>, color: vec4<f32>, frame_only: bool, hit: Hit) -> Hit {
+    let box_hit = intersect_box(ray, paddle.xyz, PADDLE_HALF_SIZE);
+    if box_hit.w >= hit.toi {
+        return hit;
+    }
+    let poi = ray.origin + ray.dir * box_hit.w;
+    if frame_only && all(abs(poi.xy - paddle.xy) < PADDLE_HALF_SIZE.xy - PADDLE_FRAME) {
+        return hit;
+    }
+    // Shaded like a sphere, walls are the only checkered surfaces
+    return Hit(box_hit.w, color.xyz, box_hit.xyz, color.w, true, NO_SPHERE);
+}
+
+// The paddles and the ball of a game, if there's one
+fn closer_game_hit(ray: Ray, t: f32, hit: Hit) -> Hit {
+    var closer = hit;
+    if params.player_paddle.w > 0.0 {
+        closer = closer_paddle_hit(ray, params.player_paddle, PLAYER_PADDLE_COLOR, true, closer);
+        closer = closer_paddle_hit(ray, params.opponent_paddle, OPPONENT_PADDLE_COLOR, false, closer);
+    }
+    if params.ball.r > 0.0 {
+        closer = closer_sphere_hit(ray, t, params.ball, NO_SPHERE, closer);
+    }
+    return closer;
+}
+
+// Shaded like the spheres too
+fn closer_primitive_hit(ray: Ray, hit: Hit) -> Hit {
+    var closer = hit;
+    for (var i = 0u; i < primitives.count; i++) {
+        let primitive = primitives.items[i];
+        let primitive_hit = intersect_primitive(ray, primitive);
+        if primitive_hit.w < closer.toi {
+            closer = Hit(primitive_hit.w, primitive.color.xyz, primitive_hit.xyz, primitive.color.w, true, NO_SPHERE);
+        }
+    }
+    return closer;
+}
+
+fn find_hit(ray: Ray, t: f32) -> Hit {
+    var hit = closer_primitive_hit(ray, closer_game_hit(ray, t, find_wall_hit(ray)));
+    for (var sphere: u32 = 0u; sphere < params.spheres_count; sphere++) {
+        hit = closer_sphere_hit(ray, t, spheres[sphere], sphere, hit);
+    }
+    return hit;
+}
+
+// Same as find_hit, but goes through the spheres in chunks shared by the tile
+fn find_hit_in_tile(ray: Ray, t: f32, local_index: u32) -> Hit {
+    var hit = closer_primitive_hit(ray, closer_game_hit(ray, t, find_wall_hit(ray)));
+    for (var base: u32 = 0u; base < params.spheres_count; base += TILE_PIXELS) {
+        workgroupBarrier();
+        if base + local_index < params.spheres_count {
+            tile_spheres[local_index] = spheres[base + local_index];
+        }
+        workgroupBarrier();
+        let count = min(TILE_PIXELS, params.spheres_count - base);
+        for (var i: u32 = 0u; i < count; i++) {
+            hit = closer_sphere_hit(ray, t, tile_spheres[i], base + i, hit);
+        }
+    }
+    return hit;
+}
+
+// Keeps going after the path is done instead of breaking out, the searches
+// have to stay in lockstep across the tile.
+fn cast_ray(in_ray: Ray, t: f32, local_index: u32) -> vec3<f32> {
+    var coef_color = vec3<f32>(1.0, 1.0, 1.0);
+    var offset_color = vec3<f32>(0.0, 0.0, 0.0);
+    var ray = in_ray;
+    var done = false;
+    bounce_count = 0u;
+    sphere_test_count = 0u;
+    for (var i = 0u; i < params.max_bounces; i++) {
+        let tests = sphere_test_count;
+        let hit = find_hit_in_tile(ray, t, local_index);
+        // Finished paths only search to keep the tile in lockstep
+        if done {
+            sphere_test_count = tests;
+        }
+        if !done && hit.toi < MAX_TOI {
+            bounce_count += 1u;
+            let poi: vec3<f32> = ray.origin + ray.dir * hit.toi;
+            offset_color += coef_color * hit.color;
+            if hit.with_sphere {
+                coef_color *= hit.refl;
+            } else {
                 let offset_poi = (poi + vec3<f32>(1000.0, 1000.0, 1000.0)) * 1.5;
                 let checkered : i32 = i32(round(offset_poi.x)) + i32(round(offset_poi.y)) + i32(round(offset_poi.z));
                 if checkered % 2 == 0 {
-                    offset_color += coef_color * color;
                     coef_color *= vec3<f32>(0.0, 0.0, 0.0);
-                    break;
+                    done = true;
+                } else {
+                    coef_color *= vec3<f32>(0.3, 0.3, 0.3);
                 }
             }
-            let reflection_dir = ray.dir - 2.0 * dot(ray.dir, normal) * normal;
+            let reflection_dir = ray.dir - 2.0 * dot(ray.dir, hit.normal) * hit.normal;
             ray.origin = poi;
             ray.dir = reflection_dir;
-            offset_color += coef_color * color;
-            if with_sphere {
-                coef_color *= refl;
-            } else {
-                coef_color *= vec3<f32>(0.3, 0.3, 0.3);
-            }
         } else {
-            break;
+            done = true;
         }
     }
     return offset_color + coef_color;
 }
 
-fn trace_for_point(coord: vec2<f32>) -> vec3<f32> {
-    return cast_ray(make_start_ray_for_point(coord));
+// Blue to green to yellow to red as the value goes from 0 to 1
+fn heatmap(value: f32) -> vec3<f32> {
+    let v = clamp(value, 0.0, 1.0) * 3.0;
+    return clamp(vec3<f32>(v - 1.0, min(v, 3.0 - v), 1.0 - v), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn index_color(index: u32) -> vec3<f32> {
+    let f = f32(index);
+    return vec3<f32>(hash(vec2<f32>(f, 1.0)), hash(vec2<f32>(f, 2.0)), hash(vec2<f32>(f, 3.0)));
+}
+
+fn debug_view_for_point(coord: vec2<f32>, local_index: u32) -> vec3<f32> {
+    let ray = make_start_ray_for_point(coord, vec2<f32>(0.0, 0.0));
+    let hit = find_hit_in_tile(ray, 0.0, local_index);
+    // Matches DebugView on the Rust side
+    switch (params.debug_view) {
+        // Normals
+        case 1u: {
+            return hit.normal * 0.5 + 0.5;
+        }
+        // Depth
+        case 2u: {
+            let depth = hit.toi * length(ray.dir);
+            return vec3<f32>(1.0 - clamp(depth / 20.0, 0.0, 1.0));
+        }
+        // Bounces
+        case 3u: {
+            cast_ray(ray, 0.0, local_index);
+            return heatmap(f32(bounce_count) / f32(params.max_bounces));
+        }
+        // Sphere index
+        case 4u: {
+            if hit.sphere != NO_SPHERE {
+                return index_color(hit.sphere);
+            }
+            return vec3<f32>(0.1, 0.1, 0.1);
+        }
+        // Sphere tests
+        case 5u: {
+            cast_ray(ray, 0.0, local_index);
+            // Every sphere and the ball on every bounce
+            let max_tests = f32(params.max_bounces) * f32(params.spheres_count + 1u);
+            return heatmap(f32(sphere_test_count) / max_tests);
+        }
+        // Velocity
+        case 6u: {
+            if hit.with_sphere && hit.sphere != NO_SPHERE {
+                return heatmap(length(spheres[hit.sphere].vel.xyz) / MAX_DEBUG_VELOCITY);
+            }
+            return vec3<f32>(0.0, 0.0, 0.0);
+        }
+        default: {
+            return vec3<f32>(1.0, 0.0, 1.0);
+        }
+    }
 }
-        
@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+
+use crate::layout;
+
+// Tiles of the ray tracing dispatch are TILE_SIZE x TILE_SIZE pixels
+pub const TILE_SIZE: u32 = 8;
+// The walls of the room the spheres bounce around in
+pub const ROOM_MIN: [f32; 3] = [-4.0, -2.0, 0.0];
+pub const ROOM_MAX: [f32; 3] = [4.0, 2.0, 16.0];
+// Half the width, height and thickness of the game's paddles
+pub const PADDLE_HALF_SIZE: [f32; 3] = [0.9, 0.6, 0.05];
+// Fills whichever of the other sphere and the wall a collision event doesn't have
+pub const NO_INDEX: u32 = u32::MAX;
+// Kinds of primitives
+pub const PRIMITIVE_PLANE: u32 = 0;
+pub const PRIMITIVE_DISK: u32 = 1;
+pub const PRIMITIVE_BOX: u32 = 2;
+pub const PRIMITIVE_ORIENTED_BOX: u32 = 3;
+// Threads of the single workgroup the physics diagnostics are reduced in
+pub const REDUCTION_SIZE: u32 = 64;
+
+// Values the shaders get from the Rust side, used like `#define`s
+fn defines() -> Vec<(String, String)> {
+    let vec3 = |v: [f32; 3]| format!("vec3<f32>({:?}, {:?}, {:?})", v[0], v[1], v[2]);
+    vec![
+        ("TILE_SIZE".to_owned(), TILE_SIZE.to_string()),
+        (
+            "TILE_PIXELS".to_owned(),
+            format!("{}u", TILE_SIZE * TILE_SIZE),
+        ),
+        ("ROOM_MIN".to_owned(), vec3(ROOM_MIN)),
+        ("ROOM_MAX".to_owned(), vec3(ROOM_MAX)),
+        ("PADDLE_HALF_SIZE".to_owned(), vec3(PADDLE_HALF_SIZE)),
+        ("NO_INDEX".to_owned(), format!("{}u", NO_INDEX)),
+        ("REDUCTION_SIZE".to_owned(), format!("{}u", REDUCTION_SIZE)),
+        (
+            "PRIMITIVE_PLANE".to_owned(),
+            format!("{}u", PRIMITIVE_PLANE),
+        ),
+        ("PRIMITIVE_DISK".to_owned(), format!("{}u", PRIMITIVE_DISK)),
+        ("PRIMITIVE_BOX".to_owned(), format!("{}u", PRIMITIVE_BOX)),
+        (
+            "PRIMITIVE_ORIENTED_BOX".to_owned(),
+            format!("{}u", PRIMITIVE_ORIENTED_BOX),
+        ),
+    ]
+}
+
+// Sources the shaders can `#include`
+fn include(name: &str) -> Option<String> {
+    match name {
+        "layout.wgsl" => Some(layout::preamble()),
+        "primitives.wgsl" => Some(include_str!("primitives.wgsl").to_owned()),
+        _ => None,
+    }
+}
+
+// Resolves the directives in a WGSL source before it's handed to naga:
+// `#include "name.wgsl"` pastes in a shared source, once per shader, and
+// `#define NAME value` replaces every NAME identifier after it with value.
+pub fn compose(source: &str) -> Result<String, String> {
+    compose_with(source, &[])
+}
+
+// Same, with `includes` in place of the embedded sources of the same names,
+// e.g. ones being edited
+pub fn compose_with(source: &str, includes: &[(&str, &str)]) -> Result<String, String> {
+    let mut composer = Composer {
+        defines: defines(),
+        includes,
+        included: HashSet::new(),
+        output: String::new(),
+    };
+    composer.compose(source)?;
+    Ok(composer.output)
+}
+
+struct Composer<'a> {
+    defines: Vec<(String, String)>,
+    includes: &'a [(&'a str, &'a str)],
+    included: HashSet<String>,
+    output: String,
+}
+
+impl Composer<'_> {
+    fn compose(&mut self, source: &str) -> Result<(), String> {
+        for line in source.lines() {
+            let Some(directive) = line.trim_start().strip_prefix('#') else {
+                let line = self.substitute(line);
+                self.output.push_str(&line);
+                self.output.push('\n');
+                continue;
+            };
+            let (name, argument) = directive
+                .split_once(char::is_whitespace)
+                .unwrap_or((directive, ""));
+            let argument = argument.trim();
+            match name {
+                "include" => {
+                    let file = argument
+                        .strip_prefix('"')
+                        .and_then(|argument| argument.strip_suffix('"'))
+                        .ok_or_else(|| format!("Expected a quoted file name in {:?}", line))?;
+                    if self.included.insert(file.to_owned()) {
+                        let source = self
+                            .includes
+                            .iter()
+                            .find(|(name, _)| *name == file)
+                            .map(|(_, source)| source.to_string())
+                            .or_else(|| include(file))
+                            .ok_or_else(|| format!("Can't include {:?}", file))?;
+                        self.compose(&source)?;
+                    }
+                }
+                "define" => {
+                    let (name, value) = argument
+                        .split_once(char::is_whitespace)
+                        .ok_or_else(|| format!("Expected a name and a value in {:?}", line))?;
+                    let value = self.substitute(value.trim());
+                    self.defines.retain(|(defined, _)| defined != name);
+                    self.defines.push((name.to_owned(), value));
+                }
+                _ => return Err(format!("Unknown directive {:?}", line)),
+            }
+        }
+        Ok(())
+    }
+
+    fn substitute(&self, line: &str) -> String {
+        let mut output = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            output.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let identifier = &rest[..end];
+            // Like the u in 64u or the e in 1e5
+            let literal_suffix = output.ends_with(|c: char| c.is_ascii_digit() || c == '.');
+            match self.defines.iter().find(|(name, _)| name == identifier) {
+                Some((_, value)) if !literal_suffix => output.push_str(value),
+                _ => output.push_str(identifier),
+            }
+            rest = &rest[end..];
+        }
+        output.push_str(rest);
+        output
+    }
+}
@@ -0,0 +1,133 @@
+use std::{convert::Infallible, mem};
+
+use crate::{
+    layout::{Sphere, MAX_SPHERES_COUNT},
+    readback::ReadbackRing,
+};
+
+// About once a second at the target frame rate
+pub const SNAPSHOT_INTERVAL: u32 = 60;
+
+// What was copied, recorded when the copy is encoded
+struct PendingCopy {
+    spheres_count: u32,
+    frame: u32,
+    requested: bool,
+    // Copies from before the last `reset` are dropped
+    generation: u32,
+}
+
+// Keeps a CPU copy of the simulation that is at most a few frames old, so the
+// spheres can be put back where they were after the GPU state is lost.
+pub struct SphereSnapshot {
+    readbacks: ReadbackRing<PendingCopy>,
+    generation: u32,
+    frames_since_copy: u32,
+    requested: bool,
+    spheres: Vec<Sphere>,
+    // Frame index the spheres are from
+    frame: u32,
+}
+
+impl SphereSnapshot {
+    pub fn new(device: &wgpu::Device, spheres: Vec<Sphere>) -> Self {
+        SphereSnapshot {
+            // The second slot is kept free for requested copies
+            readbacks: ReadbackRing::new(
+                device,
+                2,
+                (mem::size_of::<Sphere>() * MAX_SPHERES_COUNT) as _,
+            ),
+            generation: 0,
+            frames_since_copy: 0,
+            requested: false,
+            spheres,
+            frame: 0,
+        }
+    }
+
+    // Copies on the next frame instead of waiting for the interval, `poll`
+    // returns true once that copy is read back
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    // Copies the buffer the physics pass of this frame wrote every
+    // `SNAPSHOT_INTERVAL` frames or when requested, has to be followed by
+    // `start_readback` once the encoder is submitted. `frame` is the frame
+    // index the spheres are at after that pass. Returns true when this is the
+    // requested copy, so the caller can take the rest of the state from the
+    // same frame.
+    pub fn copy(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        spheres_buffer: &wgpu::Buffer,
+        spheres_count: u32,
+        frame: u32,
+    ) -> bool {
+        self.frames_since_copy += 1;
+        // A periodic copy waits for the one before it, so a requested copy
+        // never has to wait for a periodic one
+        let periodic =
+            self.frames_since_copy >= SNAPSHOT_INTERVAL && self.readbacks.in_flight() == 0;
+        if !periodic && !self.requested {
+            return false;
+        }
+        let requested = self.requested;
+        let Some(readback_buffer) = self.readbacks.next(PendingCopy {
+            spheres_count,
+            frame,
+            requested,
+            generation: self.generation,
+        }) else {
+            return false;
+        };
+        encoder.copy_buffer_to_buffer(
+            spheres_buffer,
+            0,
+            readback_buffer,
+            0,
+            (mem::size_of::<Sphere>() * spheres_count as usize) as _,
+        );
+        self.frames_since_copy = 0;
+        self.requested = false;
+        requested
+    }
+
+    pub fn start_readback(&mut self) {
+        self.readbacks.start_readback();
+    }
+
+    // Returns true when a requested snapshot has been read back
+    pub fn poll(&mut self) -> bool {
+        let mut requested = false;
+        let Ok(()) = self.readbacks.poll(|data, copy| {
+            if copy.generation == self.generation {
+                self.spheres = bytemuck::cast_slice::<u8, Sphere>(data)
+                    [..copy.spheres_count as usize]
+                    .to_vec();
+                self.frame = copy.frame;
+                requested |= copy.requested;
+            }
+            Ok::<_, Infallible>(())
+        });
+        requested
+    }
+
+    // Replaces the spheres, e.g. after loading a saved state, a copy that's
+    // still in flight is dropped
+    pub fn reset(&mut self, spheres: Vec<Sphere>, frame: u32) {
+        self.generation = self.generation.wrapping_add(1);
+        self.spheres = spheres;
+        self.frame = frame;
+        self.frames_since_copy = 0;
+    }
+
+    pub fn spheres(&self) -> &[Sphere] {
+        &self.spheres
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+}
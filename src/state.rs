@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+use crate::{
+    game::Game,
+    primitives::{self, ScenePrimitive},
+    Sphere, MAX_BOUNCES, MAX_SAMPLES_PER_PIXEL, MAX_SPHERES_COUNT,
+};
+
+// Bumped whenever a field changes meaning or goes away
+pub const STATE_VERSION: u32 = 2;
+// Version 1 also had the frame parity and the time step, which are ignored now
+const OLDEST_STATE_VERSION: u32 = 1;
+
+// Everything it takes to continue a simulation exactly where it was saved
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SimulationState {
+    pub version: u32,
+    pub seed: Option<u64>,
+    pub frame_index: u32,
+    pub camera_x: f32,
+    pub motion_blur: bool,
+    pub shutter_angle: f32,
+    pub aperture: f32,
+    pub focus_distance: f32,
+    pub samples_per_pixel: u32,
+    pub max_bounces: u32,
+    pub spheres_count: u32,
+    pub spheres: Vec<Sphere>,
+    // The scene the spheres bounce around in, older states have none
+    #[serde(default)]
+    pub primitives: Vec<ScenePrimitive>,
+    // The game in play when the state was saved, if any
+    #[serde(default)]
+    pub game: Option<Game>,
+}
+
+impl SimulationState {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|err| format!("Can't read {}: {}", path.display(), err))?;
+        let state: SimulationState = serde_json::from_str(&json)
+            .map_err(|err| format!("Can't parse {}: {}", path.display(), err))?;
+        state
+            .validate()
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(state)
+    }
+
+    // Also for the states recordings carry
+    pub fn validate(&self) -> Result<(), String> {
+        if !(OLDEST_STATE_VERSION..=STATE_VERSION).contains(&self.version) {
+            return Err(format!(
+                "State version {}, only versions {} to {} are supported",
+                self.version, OLDEST_STATE_VERSION, STATE_VERSION
+            ));
+        }
+        if self.spheres.len() != self.spheres_count as usize
+            || self.spheres.is_empty()
+            || self.spheres.len() > MAX_SPHERES_COUNT
+        {
+            return Err(format!(
+                "{} spheres out of {}, expected between 1 and {}",
+                self.spheres.len(),
+                self.spheres_count,
+                MAX_SPHERES_COUNT
+            ));
+        }
+        if !(1..=MAX_SAMPLES_PER_PIXEL).contains(&self.samples_per_pixel)
+            || !(1..=MAX_BOUNCES).contains(&self.max_bounces)
+        {
+            return Err(format!(
+                "{} samples per pixel and {} bounces, expected 1 to {} and 1 to {}",
+                self.samples_per_pixel, self.max_bounces, MAX_SAMPLES_PER_PIXEL, MAX_BOUNCES
+            ));
+        }
+        primitives::validate(&self.primitives)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).expect("Can't serialize the state") + "\n";
+        fs::write(path, json).map_err(|err| format!("Can't write {}: {}", path.display(), err))
+    }
+}
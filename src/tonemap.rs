@@ -0,0 +1,326 @@
+use bytemuck::{Pod, Zeroable};
+use std::mem;
+
+use crate::postprocess::{PostEffect, PostEffects};
+
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    Linear = 0,
+    Reinhard = 1,
+    Aces = 2,
+}
+
+impl ToneMapOperator {
+    pub fn next(self) -> Self {
+        match self {
+            ToneMapOperator::Linear => ToneMapOperator::Reinhard,
+            ToneMapOperator::Reinhard => ToneMapOperator::Aces,
+            ToneMapOperator::Aces => ToneMapOperator::Linear,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+struct ToneMapParams {
+    exposure: f32,
+    tone_map_operator: u32,
+    encode_srgb: u32,
+    effects: u32,
+    grain_strength: f32,
+    time: f32,
+    _padding: [f32; 2],
+    lut_domain_min: [f32; 3],
+    _padding_min: f32,
+    lut_domain_max: [f32; 3],
+    _padding_max: f32,
+}
+
+// Prefer formats the hardware encodes to sRGB on write, otherwise the tone
+// mapping pass does the encoding itself.
+pub fn pick_surface_format(formats: &[wgpu::TextureFormat]) -> wgpu::TextureFormat {
+    formats
+        .iter()
+        .copied()
+        .find(|format| format.describe().srgb)
+        .unwrap_or(formats[0])
+}
+
+// Screenshots are tone mapped into this, 8 bit whatever the surface is and
+// encoded the same way so both passes can share the parameters
+pub fn capture_format(output_format: wgpu::TextureFormat) -> wgpu::TextureFormat {
+    if output_format.describe().srgb {
+        wgpu::TextureFormat::Rgba8UnormSrgb
+    } else {
+        wgpu::TextureFormat::Rgba8Unorm
+    }
+}
+
+pub struct ToneMapper {
+    pipeline: wgpu::RenderPipeline,
+    capture_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    params_buffer: wgpu::Buffer,
+    lut_view: wgpu::TextureView,
+    lut_sampler: wgpu::Sampler,
+    lut_domain: ([f32; 3], [f32; 3]),
+    encode_srgb: bool,
+    pub operator: ToneMapOperator,
+    pub exposure: f32,
+    pub grain_strength: f32,
+}
+
+impl ToneMapper {
+    pub fn new(
+        device: &wgpu::Device,
+        hdr_view: &wgpu::TextureView,
+        lut_view: wgpu::TextureView,
+        lut_domain: ([f32; 3], [f32; 3]),
+        output_format: wgpu::TextureFormat,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
+                "tonemap.wgsl"
+            ))),
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: mem::size_of::<ToneMapParams>() as _,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let lut_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: None,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            mem::size_of::<ToneMapParams>() as _
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &pipeline_layout, &shader, output_format);
+        let capture_pipeline = create_pipeline(
+            device,
+            &pipeline_layout,
+            &shader,
+            capture_format(output_format),
+        );
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &params_buffer,
+            hdr_view,
+            &lut_view,
+            &lut_sampler,
+        );
+        ToneMapper {
+            pipeline,
+            capture_pipeline,
+            bind_group_layout,
+            bind_group,
+            params_buffer,
+            lut_view,
+            lut_sampler,
+            lut_domain,
+            encode_srgb: !output_format.describe().srgb,
+            operator: ToneMapOperator::Aces,
+            exposure: 1.0,
+            grain_strength: 0.05,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        params_buffer: &wgpu::Buffer,
+        hdr_view: &wgpu::TextureView,
+        lut_view: &wgpu::TextureView,
+        lut_sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(hdr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(lut_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(lut_sampler),
+                },
+            ],
+        })
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, hdr_view: &wgpu::TextureView) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.params_buffer,
+            hdr_view,
+            &self.lut_view,
+            &self.lut_sampler,
+        );
+    }
+
+    pub fn render(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+        effects: PostEffects,
+        time: f32,
+        passthrough: bool,
+    ) {
+        // Passthrough keeps the colors as they are, e.g. for debug views
+        let (exposure, operator) = if passthrough {
+            (1.0, ToneMapOperator::Linear)
+        } else {
+            (self.exposure, self.operator)
+        };
+        queue.write_buffer(
+            &self.params_buffer,
+            0,
+            bytemuck::cast_slice(&[ToneMapParams {
+                exposure,
+                tone_map_operator: operator as u32,
+                encode_srgb: self.encode_srgb as u32,
+                effects: effects.bits()
+                    & (PostEffect::FilmGrain as u32 | PostEffect::ColorGrading as u32),
+                grain_strength: self.grain_strength,
+                time,
+                _padding: [0.0; 2],
+                lut_domain_min: self.lut_domain.0,
+                _padding_min: 0.0,
+                lut_domain_max: self.lut_domain.1,
+                _padding_max: 0.0,
+            }]),
+        );
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+
+    // Draws the frame `render` just drew once more, into a texture of the
+    // capture format
+    pub fn render_capture(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+    ) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(&self.capture_pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(format.into())],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
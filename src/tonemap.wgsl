@@ -0,0 +1,106 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    // Single triangle covering the whole screen
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    var result: VertexOutput;
+    result.position = vec4<f32>(x, y, 0.0, 1.0);
+    return result;
+}
+
+struct ToneMapParams {
+    exposure: f32,
+    tone_map_operator: u32,
+    encode_srgb: u32,
+    effects: u32,
+    grain_strength: f32,
+    time: f32,
+    _padding: vec2<f32>,
+    // The input colors the LUT spans
+    lut_domain_min: vec3<f32>,
+    lut_domain_max: vec3<f32>,
+};
+
+// Matches the PostEffect bits on the Rust side
+const EFFECT_FILM_GRAIN: u32 = 8u;
+const EFFECT_COLOR_GRADING: u32 = 16u;
+
+@group(0) @binding(0) var<uniform> params: ToneMapParams;
+@group(0) @binding(1) var hdr_texture: texture_2d<f32>;
+@group(0) @binding(2) var lut_texture: texture_3d<f32>;
+@group(0) @binding(3) var lut_sampler: sampler;
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (vec3<f32>(1.0, 1.0, 1.0) + color);
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+fn grade(color: vec3<f32>) -> vec3<f32> {
+    let input = clamp((color - params.lut_domain_min) / (params.lut_domain_max - params.lut_domain_min), vec3<f32>(0.0), vec3<f32>(1.0));
+    // Sample texel centers so the LUT ends map exactly to the domain's ends
+    let size = f32(textureDimensions(lut_texture).x);
+    let coord = input * ((size - 1.0) / size) + 0.5 / size;
+    return textureSampleLevel(lut_texture, lut_sampler, coord, 0.0).rgb;
+}
+
+fn hash(p: vec3<f32>) -> f32 {
+    let q = fract(p * 0.1031);
+    let r = q + dot(q, q.zyx + 31.32);
+    return fract((r.x + r.y) * r.z);
+}
+
+@fragment
+fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let hdr = textureLoad(hdr_texture, vec2<i32>(vertex.position.xy), 0).rgb * params.exposure;
+    var color: vec3<f32>;
+    // Matches ToneMapOperator on the Rust side
+    switch (params.tone_map_operator) {
+        case 1u: {
+            color = reinhard(hdr);
+        }
+        case 2u: {
+            color = aces(hdr);
+        }
+        default: {
+            color = hdr;
+        }
+    }
+    color = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+    // .cube grades are made for display encoded colors
+    if (params.effects & EFFECT_COLOR_GRADING) != 0u {
+        color = srgb_to_linear(grade(linear_to_srgb(color)));
+    }
+    if (params.effects & EFFECT_FILM_GRAIN) != 0u {
+        let noise = hash(vec3<f32>(vertex.position.xy, fract(params.time) * 1000.0)) - 0.5;
+        color = clamp(color + noise * params.grain_strength, vec3<f32>(0.0), vec3<f32>(1.0));
+    }
+    if params.encode_srgb != 0u {
+        color = linear_to_srgb(color);
+    }
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,44 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    // Single triangle covering the whole screen
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    var result: VertexOutput;
+    result.position = vec4<f32>(x, y, 0.0, 1.0);
+    return result;
+}
+
+struct UpscaleParams {
+    output_size: vec2<f32>,
+    upscale_filter: u32,
+    sharpness: f32,
+};
+
+@group(0) @binding(0) var<uniform> params: UpscaleParams;
+@group(0) @binding(1) var input_sampler: sampler;
+@group(0) @binding(2) var input_texture: texture_2d<f32>;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uv = in.position.xy / params.output_size;
+    let center = textureSample(input_texture, input_sampler, uv).rgb;
+    // Matches UpscaleFilter::Bilinear on the Rust side
+    if params.upscale_filter == 0u {
+        return vec4<f32>(center, 1.0);
+    }
+    // Unsharp mask over the input texel neighbourhood, clamped to its range
+    // so it can't ring, in the spirit of FSR's RCAS
+    let texel = 1.0 / vec2<f32>(textureDimensions(input_texture));
+    let left = textureSample(input_texture, input_sampler, uv - vec2<f32>(texel.x, 0.0)).rgb;
+    let right = textureSample(input_texture, input_sampler, uv + vec2<f32>(texel.x, 0.0)).rgb;
+    let up = textureSample(input_texture, input_sampler, uv - vec2<f32>(0.0, texel.y)).rgb;
+    let down = textureSample(input_texture, input_sampler, uv + vec2<f32>(0.0, texel.y)).rgb;
+    let lowest = min(min(min(left, right), min(up, down)), center);
+    let highest = max(max(max(left, right), max(up, down)), center);
+    let sharpened = center + (4.0 * center - left - right - up - down) * params.sharpness;
+    return vec4<f32>(clamp(sharpened, lowest, highest), 1.0);
+}
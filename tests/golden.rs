@@ -0,0 +1,222 @@
+// Renders fixed scenes with `wroom-ten export` on the software fallback adapter
+// and compares them to the references in tests/golden. Differing frames are
+// written next to a diff image under the target directory. To accept new
+// output, run with WROOM_BLESS=1 and commit the updated references.
+
+use serde_json::{json, Value};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 90;
+// Differences up to this much per channel are rounding between drivers
+const CHANNEL_TOLERANCE: u8 = 8;
+// Fraction of the pixels allowed past the channel tolerance
+const PIXEL_TOLERANCE: f64 = 0.005;
+
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+fn read_png(path: &Path) -> Image {
+    let decoder = png::Decoder::new(fs::File::open(path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!(info.color_type, png::ColorType::Rgba, "{}", path.display());
+    pixels.truncate(info.buffer_size());
+    Image {
+        width: info.width,
+        height: info.height,
+        pixels,
+    }
+}
+
+fn write_png(path: &Path, image: &Image) {
+    let mut encoder = png::Encoder::new(fs::File::create(path).unwrap(), image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&image.pixels).unwrap();
+}
+
+fn sphere(pos: [f32; 3], r: f32, color: [f32; 4]) -> Value {
+    json!({ "pos": pos, "r": r, "vel": [0.0, 0.0, 0.0, 0.0], "color": color })
+}
+
+// A state file as F5 saves it, with resting spheres
+fn scene_state(spheres: Vec<Value>) -> Value {
+    json!({
+        "version": 2,
+        "seed": 1,
+        "frame_index": 0,
+        "camera_x": 0.0,
+        "motion_blur": false,
+        "shutter_angle": 180.0,
+        "aperture": 0.0,
+        "focus_distance": 5.0,
+        "samples_per_pixel": 4,
+        "max_bounces": 5,
+        "spheres_count": spheres.len(),
+        "spheres": spheres,
+    })
+}
+
+// Renders a single frame, None when the GPU tests are skipped
+fn render(name: &str, scene_args: &[&str]) -> Option<Image> {
+    if env::var_os("WROOM_SKIP_GPU_TESTS").is_some() {
+        eprintln!("Skipping {}, WROOM_SKIP_GPU_TESTS is set", name);
+        return None;
+    }
+    let directory = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("golden")
+        .join(name);
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_wroom-ten"))
+        .arg("--fallback-adapter")
+        .arg("export")
+        .arg(directory.join("frames"))
+        .args(["--resolution", &format!("{}x{}", WIDTH, HEIGHT)])
+        .args(["--fps", "50", "--duration", "0.02", "--samples", "4"])
+        .args(scene_args)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        !stderr.contains("Can't find a suitable adapter"),
+        "There's no fallback adapter to render {} on, set WROOM_SKIP_GPU_TESTS=1 to skip the GPU tests",
+        name
+    );
+    assert!(
+        output.status.success(),
+        "Can't render {}:\n{}",
+        name,
+        stderr
+    );
+    Some(read_png(&directory.join("frames").join("frame-00000.png")))
+}
+
+fn render_state(name: &str, state: Value) -> Option<Image> {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.json", name));
+    fs::write(&path, serde_json::to_string_pretty(&state).unwrap()).unwrap();
+    render(name, &["--state", path.to_str().unwrap()])
+}
+
+fn reference_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.png", name))
+}
+
+fn check(name: &str, actual: Option<Image>) {
+    let Some(actual) = actual else {
+        return;
+    };
+    let reference_path = reference_path(name);
+    if env::var_os("WROOM_BLESS").is_some() {
+        fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+        write_png(&reference_path, &actual);
+        return;
+    }
+    let reference = read_png(&reference_path);
+    assert_eq!(
+        (reference.width, reference.height),
+        (actual.width, actual.height),
+        "{} changed size",
+        name
+    );
+    // Differing pixels in red over a dimmed copy of the reference
+    let mut diff = Vec::with_capacity(reference.pixels.len());
+    let mut differing = 0;
+    for (expected, actual) in reference.pixels.chunks(4).zip(actual.pixels.chunks(4)) {
+        let over = expected
+            .iter()
+            .zip(actual)
+            .any(|(expected, actual)| expected.abs_diff(*actual) > CHANNEL_TOLERANCE);
+        if over {
+            differing += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            diff.extend(expected[..3].iter().map(|channel| channel / 4));
+            diff.push(255);
+        }
+    }
+    let fraction = differing as f64 / (reference.width * reference.height) as f64;
+    if fraction > PIXEL_TOLERANCE {
+        let directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        let actual_path = directory.join(format!("{}-actual.png", name));
+        let diff_path = directory.join(format!("{}-diff.png", name));
+        write_png(&actual_path, &actual);
+        write_png(
+            &diff_path,
+            &Image {
+                width: reference.width,
+                height: reference.height,
+                pixels: diff,
+            },
+        );
+        panic!(
+            "{} differs from {} in {:.2}% of the pixels, see {} and {}",
+            name,
+            reference_path.display(),
+            fraction * 100.0,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+#[test]
+fn walls() {
+    // A speck in the far corner, spheres_count can't be zero
+    let state = scene_state(vec![sphere([3.5, 1.5, 15.0], 0.02, [0.0; 4])]);
+    check("walls", render_state("walls", state));
+}
+
+#[test]
+fn seeded_spheres() {
+    let scene = render("seeded_spheres", &["--seed", "7", "--spheres", "100"]);
+    check("seeded_spheres", scene);
+}
+
+#[test]
+fn reflections() {
+    let state = scene_state(vec![
+        sphere([0.0, 0.0, 6.0], 1.2, [0.02, 0.02, 0.02, 0.9]),
+        sphere([-2.0, -1.0, 4.0], 0.4, [0.8, 0.1, 0.1, 0.2]),
+        sphere([2.0, 1.0, 4.0], 0.4, [0.1, 0.1, 0.8, 0.2]),
+    ]);
+    check("reflections", render_state("reflections", state));
+}
+
+#[test]
+fn sphere_occlusion() {
+    let state = scene_state(vec![
+        sphere([-0.4, 0.0, 5.0], 0.8, [0.8, 0.1, 0.1, 0.0]),
+        sphere([0.4, 0.2, 7.0], 1.0, [0.1, 0.8, 0.1, 0.0]),
+    ]);
+    check("sphere_occlusion", render_state("sphere_occlusion", state));
+}
+
+#[test]
+fn primitives() {
+    let mut state = scene_state(vec![sphere([0.5, -0.3, 5.0], 0.3, [0.8, 0.1, 0.1, 0.2])]);
+    state["primitives"] = json!([
+        { "shape": "plane", "point": [0.0, -1.8, 0.0], "normal": [0.0, 1.0, -0.15],
+          "color": [0.3, 0.3, 0.35, 0.4] },
+        { "shape": "disk", "center": [-2.0, 0.5, 7.0], "normal": [0.6, 0.0, -1.0], "radius": 0.9,
+          "color": [0.9, 0.6, 0.1, 0.2] },
+        { "shape": "box", "center": [2.0, -0.5, 6.0], "half_size": [0.5, 0.8, 0.5],
+          "color": [0.1, 0.6, 0.3, 0.3] },
+        { "shape": "oriented_box", "center": [0.0, 0.8, 9.0], "half_size": [1.2, 0.2, 0.6],
+          "rotation": [20.0, 35.0, 15.0], "color": [0.2, 0.3, 0.9, 0.5] },
+    ]);
+    check("primitives", render_state("primitives", state));
+}
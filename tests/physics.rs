@@ -0,0 +1,671 @@
+// Runs the physics pass from compute.wgsl on the software fallback adapter
+// over random sphere configurations and checks what it writes back
+#[allow(dead_code)]
+#[path = "../src/layout.rs"]
+mod layout;
+#[allow(dead_code)]
+#[path = "../src/primitives.rs"]
+mod primitives;
+#[allow(dead_code)]
+#[path = "../src/shaders.rs"]
+mod shaders;
+
+use bytemuck::Zeroable;
+use layout::{CollisionEvent, RayTracingParams, Sphere};
+use pollster::FutureExt as _;
+use primitives::{ScenePrimitive, Shape};
+use proptest::{prelude::*, test_runner::FileFailurePersistence};
+use shaders::{ROOM_MAX, ROOM_MIN};
+use std::{
+    borrow::Cow,
+    env, mem,
+    sync::{Mutex, OnceLock},
+};
+use wgpu::util::DeviceExt as _;
+
+const MAX_SPHERES: usize = 24;
+// The event array follows the atomic counter at the alignment of the events
+const EVENTS_OFFSET: usize = 16;
+
+struct Physics {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl Physics {
+    fn new() -> Self {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                force_fallback_adapter: true,
+                compatible_surface: None,
+            })
+            .block_on()
+            .expect(
+                "Can't find a fallback adapter, set WROOM_SKIP_GPU_TESTS=1 to skip the GPU tests",
+            );
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: wgpu::Features::empty(),
+                    limits: adapter.limits(),
+                },
+                None,
+            )
+            .block_on()
+            .expect("Can't create a device on the fallback adapter");
+        let source = shaders::compose(include_str!("../src/compute.wgsl")).unwrap();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(source)),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: None,
+            layout: None,
+            module: &shader,
+            entry_point: "main",
+        });
+        Physics {
+            device,
+            queue,
+            pipeline,
+        }
+    }
+
+    // One physics step as the renderer dispatches it
+    fn step(
+        &self,
+        spheres: &[Sphere],
+        primitives: &[ScenePrimitive],
+        time_elapsed: f32,
+    ) -> Vec<Sphere> {
+        let params = RayTracingParams {
+            spheres_count: spheres.len() as u32,
+            time_elapsed,
+            ..RayTracingParams::zeroed()
+        };
+        let storage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC;
+        let uniform_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::bytes_of(&params),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let spheres_in = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(spheres),
+                usage: storage,
+            });
+        let spheres_size = mem::size_of_val(spheres) as wgpu::BufferAddress;
+        let spheres_out = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: spheres_size,
+            usage: storage,
+            mapped_at_creation: false,
+        });
+        let collision_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (EVENTS_OFFSET + mem::size_of::<CollisionEvent>() * spheres.len()) as _,
+            usage: storage,
+            mapped_at_creation: false,
+        });
+        // The renderer's buffer always has room for all of them
+        let mut contents = primitives::buffer_contents(primitives);
+        contents.resize(primitives::PRIMITIVES_BUFFER_SIZE, 0);
+        let primitives_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: &contents,
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+        let readback_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: spheres_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: spheres_in.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: spheres_out.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: collision_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: primitives_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut cpass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            cpass.set_pipeline(&self.pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+            cpass.dispatch_workgroups(spheres.len() as u32, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&spheres_out, 0, &readback_buffer, 0, spheres_size);
+        self.queue.submit(Some(encoder.finish()));
+        let slice = readback_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| {
+            result.expect("Can't map the readback buffer")
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        let spheres = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        spheres
+    }
+}
+
+// Shared by all the cases, None when the GPU tests are skipped
+fn physics() -> Option<&'static Mutex<Physics>> {
+    static PHYSICS: OnceLock<Mutex<Physics>> = OnceLock::new();
+    if env::var_os("WROOM_SKIP_GPU_TESTS").is_some() {
+        eprintln!("Skipping the physics tests, WROOM_SKIP_GPU_TESTS is set");
+        return None;
+    }
+    Some(PHYSICS.get_or_init(|| Mutex::new(Physics::new())))
+}
+
+fn step(spheres: &[Sphere], time_elapsed: f32) -> Option<Vec<Sphere>> {
+    step_among(spheres, &[], time_elapsed)
+}
+
+fn step_among(
+    spheres: &[Sphere],
+    primitives: &[ScenePrimitive],
+    time_elapsed: f32,
+) -> Option<Vec<Sphere>> {
+    let physics = physics()?.lock().unwrap();
+    Some(physics.step(spheres, primitives, time_elapsed))
+}
+
+fn sphere(pos: [f32; 3], r: f32, vel: [f32; 3]) -> Sphere {
+    Sphere {
+        pos,
+        r,
+        vel: [vel[0], vel[1], vel[2], 0.0],
+        color: [0.5, 0.5, 0.5, 0.0],
+    }
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (0..3).map(|i| (a[i] - b[i]).powi(2)).sum::<f32>().sqrt()
+}
+
+// Solid spheres of unit density, as in the diagnostics
+fn mass(sphere: &Sphere) -> f64 {
+    4.0 / 3.0 * std::f64::consts::PI * (sphere.r as f64).powi(3)
+}
+
+fn speed(sphere: &Sphere) -> f64 {
+    sphere.vel[..3]
+        .iter()
+        .map(|v| (*v as f64).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+fn kinetic_energy(spheres: &[Sphere]) -> f64 {
+    spheres
+        .iter()
+        .map(|sphere| 0.5 * mass(sphere) * speed(sphere).powi(2))
+        .sum()
+}
+
+fn momentum(spheres: &[Sphere]) -> [f64; 3] {
+    [0, 1, 2].map(|i| {
+        spheres
+            .iter()
+            .map(|sphere| mass(sphere) * sphere.vel[i] as f64)
+            .sum()
+    })
+}
+
+fn radius() -> impl Strategy<Value = f32> {
+    0.05f32..0.4
+}
+
+fn velocity() -> impl Strategy<Value = [f32; 3]> {
+    [-3.0f32..3.0, -3.0f32..3.0, -3.0f32..3.0]
+}
+
+// Frame times from a fast monitor down to a hitch
+fn time_elapsed() -> impl Strategy<Value = f32> {
+    0.001f32..0.1
+}
+
+// Anywhere the sphere fits in the room, possibly overlapping others
+fn sphere_in_room() -> impl Strategy<Value = Sphere> {
+    (radius(), 0.0f32..1.0, 0.0f32..1.0, 0.0f32..1.0, velocity()).prop_map(|(r, x, y, z, vel)| {
+        let t = [x, y, z];
+        let pos = [0, 1, 2].map(|i| ROOM_MIN[i] + r + t[i] * (ROOM_MAX[i] - ROOM_MIN[i] - 2.0 * r));
+        sphere(pos, r, vel)
+    })
+}
+
+// Two spheres in the middle of the room that will touch during the step
+fn colliding_pair() -> impl Strategy<Value = (Vec<Sphere>, f32)> {
+    (
+        radius(),
+        radius(),
+        [-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0],
+        0.0f32..1.0,
+        0.5f32..3.0,
+        velocity(),
+        time_elapsed(),
+    )
+        .prop_filter_map(
+            "The spheres need a direction to approach along",
+            |(r_a, r_b, direction, gap, closing_speed, drift, time_elapsed)| {
+                let length = distance(direction, [0.0; 3]);
+                if length < 0.1 {
+                    return None;
+                }
+                let direction = direction.map(|d| d / length);
+                // Closes the gap in time and then some, but not so much that
+                // one passes through the other
+                let gap = gap * closing_speed * time_elapsed;
+                let distance = r_a + r_b + gap;
+                let center = [0.0, 0.0, 8.0];
+                let pos_a = [0, 1, 2].map(|i| center[i] - direction[i] * distance / 2.0);
+                let pos_b = [0, 1, 2].map(|i| center[i] + direction[i] * distance / 2.0);
+                let drift = drift.map(|d| d * 0.2);
+                let vel_a = [0, 1, 2].map(|i| drift[i] + direction[i] * closing_speed / 2.0);
+                let vel_b = [0, 1, 2].map(|i| drift[i] - direction[i] * closing_speed / 2.0);
+                if closing_speed * time_elapsed > r_a.min(r_b) {
+                    return None;
+                }
+                Some((
+                    vec![sphere(pos_a, r_a, vel_a), sphere(pos_b, r_b, vel_b)],
+                    time_elapsed,
+                ))
+            },
+        )
+}
+
+fn unit_vector() -> impl Strategy<Value = [f32; 3]> {
+    [-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0].prop_filter_map(
+        "The direction needs a length to normalize",
+        |v| {
+            let length = distance(v, [0.0; 3]);
+            (length > 0.1).then(|| v.map(|v| v / length))
+        },
+    )
+}
+
+// A primitive in the middle of the room, a direction away from one of its
+// faces and how far its surface is from the center that way
+fn primitive_face() -> impl Strategy<Value = (ScenePrimitive, [f32; 3], f32)> {
+    let center = [0.0, 0.0, 8.0];
+    let color = [0.5, 0.5, 0.5, 0.0];
+    let size = 0.1f32..1.0;
+    prop_oneof![
+        unit_vector().prop_map(move |normal| {
+            let shape = Shape::Plane {
+                point: center,
+                normal,
+            };
+            (ScenePrimitive { shape, color }, normal, 0.0)
+        }),
+        (unit_vector(), size.clone(), any::<bool>()).prop_map(move |(normal, radius, back)| {
+            let shape = Shape::Disk {
+                center,
+                normal,
+                radius,
+            };
+            // Disks are hit from either side
+            let face = if back { normal.map(|n| -n) } else { normal };
+            (ScenePrimitive { shape, color }, face, 0.0)
+        }),
+        ([size.clone(), size.clone(), size.clone()], 0..6usize).prop_map(
+            move |(half_size, face)| {
+                let shape = Shape::Box { center, half_size };
+                let mut normal = [0.0; 3];
+                normal[face / 2] = if face % 2 == 0 { 1.0 } else { -1.0 };
+                (ScenePrimitive { shape, color }, normal, half_size[face / 2])
+            }
+        ),
+        ([size.clone(), size.clone(), size], -180.0f32..180.0).prop_map(
+            move |(half_size, angle)| {
+                let shape = Shape::OrientedBox {
+                    center,
+                    half_size,
+                    rotation: [0.0, 0.0, angle],
+                };
+                let angle = angle.to_radians();
+                let normal = [angle.cos(), angle.sin(), 0.0];
+                (ScenePrimitive { shape, color }, normal, half_size[0])
+            }
+        ),
+    ]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (0..3).map(|i| a[i] * b[i]).sum()
+}
+
+// The whole sphere, the shader clamps the centers to `ROOM_MIN + r` and
+// `ROOM_MAX - r`
+fn inside_room(sphere: &Sphere) -> bool {
+    const EPSILON: f32 = 1e-4;
+    (0..3).all(|i| {
+        sphere.pos[i] - sphere.r >= ROOM_MIN[i] - EPSILON
+            && sphere.pos[i] + sphere.r <= ROOM_MAX[i] + EPSILON
+    })
+}
+
+// Not touching any wall, so nothing bounced off one
+fn clear_of_walls(sphere: &Sphere) -> bool {
+    const MARGIN: f32 = 1e-3;
+    (0..3).all(|i| {
+        sphere.pos[i] - sphere.r > ROOM_MIN[i] + MARGIN
+            && sphere.pos[i] + sphere.r < ROOM_MAX[i] - MARGIN
+    })
+}
+
+proptest! {
+    // Integration tests don't have the layout `SourceParallel` looks for
+    #![proptest_config(ProptestConfig {
+        failure_persistence: Some(Box::new(FileFailurePersistence::Direct(
+            "tests/physics.proptest-regressions",
+        ))),
+        ..ProptestConfig::default()
+    })]
+
+    #[test]
+    fn spheres_stay_inside_the_room(
+        spheres in prop::collection::vec(sphere_in_room(), 1..=MAX_SPHERES),
+        time_elapsed in time_elapsed(),
+    ) {
+        let mut spheres = spheres;
+        for _ in 0..4 {
+            let Some(stepped) = step(&spheres, time_elapsed) else {
+                return Ok(());
+            };
+            spheres = stepped;
+            for (index, sphere) in spheres.iter().enumerate() {
+                prop_assert!(inside_room(sphere), "Sphere {} left the room: {:?}", index, sphere);
+            }
+        }
+    }
+
+    #[test]
+    fn velocities_stay_finite(
+        spheres in prop::collection::vec(sphere_in_room(), 1..=MAX_SPHERES),
+        time_elapsed in time_elapsed(),
+    ) {
+        let Some(stepped) = step(&spheres, time_elapsed) else {
+            return Ok(());
+        };
+        for (index, sphere) in stepped.iter().enumerate() {
+            prop_assert!(
+                sphere.pos.iter().chain(&sphere.vel).all(|v| v.is_finite()),
+                "Sphere {} isn't finite: {:?}",
+                index,
+                sphere
+            );
+        }
+    }
+
+    #[test]
+    fn collided_spheres_move_apart((spheres, time_elapsed) in colliding_pair()) {
+        let Some(collided) = step(&spheres, time_elapsed) else {
+            return Ok(());
+        };
+        let Some(after) = step(&collided, time_elapsed) else {
+            return Ok(());
+        };
+        let (r_a, r_b) = (spheres[0].r, spheres[1].r);
+        let collided_distance = distance(collided[0].pos, collided[1].pos);
+        prop_assert!(
+            collided_distance >= (r_a + r_b) * 0.999,
+            "The spheres still overlap after the collision: {:?}",
+            collided
+        );
+        let after_distance = distance(after[0].pos, after[1].pos);
+        prop_assert!(
+            after_distance >= collided_distance * 0.999,
+            "The spheres got closer after the collision, {} to {}: {:?}",
+            collided_distance,
+            after_distance,
+            after
+        );
+    }
+
+    // Any two spheres closing in on each other, with their own radii and
+    // velocities. Mirroring every velocity keeps each sphere's speed, so no
+    // collision loses energy, but the momentum changes unless the spheres are
+    // equal and meet head-on. A sphere that touches a wall bounces off the
+    // wall instead of the other sphere, those cases are left out.
+    #[test]
+    fn separated_pairs_keep_apart_and_keep_their_energy(
+        (r_a, r_b) in (radius(), radius()),
+        direction in unit_vector(),
+        (vel_a, vel_b) in (velocity(), velocity()),
+        depth in 0.1f32..1.0,
+        time_elapsed in time_elapsed(),
+    ) {
+        let relative = [0, 1, 2].map(|i| vel_a[i] - vel_b[i]);
+        // From a to b, so b is where a is heading
+        let direction = if dot(relative, direction) < 0.0 {
+            direction.map(|d| -d)
+        } else {
+            direction
+        };
+        let closing_speed = dot(relative, direction);
+        prop_assume!(closing_speed > 0.1);
+        // Overlapping along the direction at the end of the step, which is
+        // all the shader looks at, and apart at its start
+        let depth = depth * (closing_speed * time_elapsed).min(r_a.min(r_b));
+        let offset = [0, 1, 2]
+            .map(|i| direction[i] * (r_a + r_b - depth) + relative[i] * time_elapsed);
+        let center = [0.0, 0.0, 8.0];
+        let spheres = [
+            sphere(
+                [0, 1, 2].map(|i| center[i] - offset[i] / 2.0),
+                r_a,
+                vel_a,
+            ),
+            sphere(
+                [0, 1, 2].map(|i| center[i] + offset[i] / 2.0),
+                r_b,
+                vel_b,
+            ),
+        ];
+        let Some(collided) = step(&spheres, time_elapsed) else {
+            return Ok(());
+        };
+        let Some(after) = step(&collided, time_elapsed) else {
+            return Ok(());
+        };
+        prop_assume!(collided.iter().chain(&after).all(clear_of_walls));
+        let collided_distance = distance(collided[0].pos, collided[1].pos);
+        prop_assert!(
+            collided_distance >= (r_a + r_b) * 0.999,
+            "The spheres still overlap after the collision: {:?}",
+            collided
+        );
+        let after_distance = distance(after[0].pos, after[1].pos);
+        prop_assert!(
+            after_distance >= collided_distance * 0.999,
+            "The spheres got closer after the collision, {} to {}: {:?}",
+            collided_distance,
+            after_distance,
+            after
+        );
+        let (before, after) = (kinetic_energy(&spheres), kinetic_energy(&after));
+        prop_assert!(
+            (after - before).abs() <= before * 1e-4,
+            "Kinetic energy went from {} to {}",
+            before,
+            after
+        );
+    }
+
+    #[test]
+    fn spheres_bounce_off_primitives(
+        (primitive, normal, extent) in primitive_face(),
+        r in radius(),
+        speed in 0.5f32..3.0,
+        gap in 0.0f32..1.0,
+        time_elapsed in time_elapsed(),
+    ) {
+        // Fast enough to touch the face during the step, not to tunnel through
+        prop_assume!(speed * time_elapsed < r);
+        let center = [0.0, 0.0, 8.0];
+        let distance = extent + r + gap * speed * time_elapsed;
+        let pos = [0, 1, 2].map(|i| center[i] + normal[i] * distance);
+        let spheres = [sphere(pos, r, normal.map(|n| -n * speed))];
+        let Some(stepped) = step_among(&spheres, &[primitive], time_elapsed) else {
+            return Ok(());
+        };
+        let sphere = stepped[0];
+        let offset = [0, 1, 2].map(|i| sphere.pos[i] - center[i]);
+        prop_assert!(
+            dot(offset, normal) >= extent + r * 0.999,
+            "The sphere is inside the primitive: {:?}",
+            sphere
+        );
+        prop_assert!(
+            dot([sphere.vel[0], sphere.vel[1], sphere.vel[2]], normal) > 0.0,
+            "The sphere didn't bounce off: {:?}",
+            sphere
+        );
+    }
+
+    // Each sphere mirrors its own velocity along the normal, which conserves
+    // momentum only for equal spheres seen from where the pair's momentum is
+    // zero, so that's the only case checked
+    #[test]
+    fn equal_mass_head_on_collisions_conserve_momentum_and_energy(
+        r in radius(),
+        direction in unit_vector(),
+        closing_speed in 0.5f32..3.0,
+        drift in velocity(),
+        // Short of 1, where they only just touch and rounding keeps them apart
+        gap in 0.0f32..0.9,
+        time_elapsed in time_elapsed(),
+    ) {
+        // Touching during the step without passing through each other
+        prop_assume!(closing_speed * time_elapsed < r);
+        let center = [0.0, 0.0, 8.0];
+        let distance = 2.0 * r + gap * closing_speed * time_elapsed;
+        // Sliding along together, across the line between the centers
+        let along = dot(drift, direction);
+        let drift = [0, 1, 2].map(|i| (drift[i] - direction[i] * along) * 0.2);
+        let offset = direction.map(|d| d * distance / 2.0);
+        let approach = direction.map(|d| d * closing_speed / 2.0);
+        let spheres = [
+            sphere(
+                [0, 1, 2].map(|i| center[i] - offset[i]),
+                r,
+                [0, 1, 2].map(|i| drift[i] + approach[i]),
+            ),
+            sphere(
+                [0, 1, 2].map(|i| center[i] + offset[i]),
+                r,
+                [0, 1, 2].map(|i| drift[i] - approach[i]),
+            ),
+        ];
+        let Some(stepped) = step(&spheres, time_elapsed) else {
+            return Ok(());
+        };
+        let separating = [0, 1, 2].map(|i| stepped[1].vel[i] - stepped[0].vel[i]);
+        prop_assert!(
+            dot(separating, direction) > 0.0,
+            "The spheres didn't bounce off each other: {:?}",
+            stepped
+        );
+        let (before, after) = (momentum(&spheres), momentum(&stepped));
+        let scale = spheres.iter().map(|sphere| mass(sphere) * speed(sphere)).sum::<f64>();
+        for i in 0..3 {
+            prop_assert!(
+                (after[i] - before[i]).abs() <= scale * 1e-4,
+                "Momentum went from {:?} to {:?}",
+                before,
+                after
+            );
+        }
+        let (before, after) = (kinetic_energy(&spheres), kinetic_energy(&stepped));
+        prop_assert!(
+            (after - before).abs() <= before * 1e-4,
+            "Kinetic energy went from {} to {}",
+            before,
+            after
+        );
+    }
+}
+
+#[test]
+fn coincident_spheres_stay_finite() {
+    let spheres = [
+        sphere([0.0, 0.0, 8.0], 0.5, [1.0, 0.0, 0.0]),
+        sphere([0.0, 0.0, 8.0], 0.5, [1.0, 0.0, 0.0]),
+    ];
+    let Some(stepped) = step(&spheres, 0.01) else {
+        return;
+    };
+    for sphere in stepped {
+        assert!(
+            sphere.pos.iter().chain(&sphere.vel).all(|v| v.is_finite()),
+            "{:?}",
+            sphere
+        );
+    }
+}
+
+// Touching the floor is a contact too, the box next to it still has to push
+// the sphere out
+#[test]
+fn primitives_are_resolved_after_other_contacts() {
+    let r = 0.3;
+    let primitive = ScenePrimitive {
+        shape: Shape::Box {
+            center: [0.0, ROOM_MIN[1] + 0.5, 8.0],
+            half_size: [0.5, 0.5, 0.5],
+        },
+        color: [0.5, 0.5, 0.5, 0.0],
+    };
+    let spheres = [sphere(
+        [0.5 + r * 0.8, ROOM_MIN[1] + r * 0.9, 8.0],
+        r,
+        [-1.0, 0.0, 0.0],
+    )];
+    let Some(stepped) = step_among(&spheres, &[primitive], 0.01) else {
+        return;
+    };
+    let sphere = stepped[0];
+    assert!(
+        sphere.pos[0] >= 0.5 + r * 0.999,
+        "The sphere is inside the box: {:?}",
+        sphere
+    );
+    assert!(
+        sphere.vel[0] > 0.0,
+        "The sphere didn't bounce off: {:?}",
+        sphere
+    );
+}
@@ -0,0 +1,83 @@
+// These modules don't depend on the rest of the binary, so they're pulled in
+// directly
+#[allow(dead_code)]
+#[path = "../src/layout.rs"]
+mod layout;
+#[allow(dead_code)]
+#[path = "../src/shaders.rs"]
+mod shaders;
+
+#[test]
+fn compute_shader_matches_rust_layouts() {
+    let source = shaders::compose(include_str!("../src/compute.wgsl")).unwrap();
+    layout::check(&source).unwrap();
+}
+
+#[test]
+fn trace_shader_matches_rust_layouts() {
+    let source = shaders::compose(include_str!("../src/shader.wgsl")).unwrap();
+    layout::check(&source).unwrap();
+}
+
+#[test]
+fn diagnostics_shader_matches_rust_layouts() {
+    let source = shaders::compose(include_str!("../src/diagnostics.wgsl")).unwrap();
+    layout::check(&source).unwrap();
+}
+
+#[test]
+fn differently_sized_field_is_caught() {
+    let preamble = layout::preamble().replace("vel: vec4<f32>", "vel: vec3<f32>");
+    let err = layout::check(&preamble).unwrap_err();
+    assert!(err.contains("Sphere.vel"), "{}", err);
+}
+
+#[test]
+fn differently_aligned_field_is_caught() {
+    // A vec3 after a scalar starts at the next 16 byte boundary in WGSL
+    let preamble = layout::preamble().replace(
+        "    pos: vec3<f32>,\n    r: f32,",
+        "    r: f32,\n    pos: vec3<f32>,",
+    );
+    let err = layout::check(&preamble).unwrap_err();
+    assert!(err.contains("Sphere"), "{}", err);
+}
+
+#[test]
+fn missing_struct_is_caught() {
+    let preamble = layout::preamble();
+    let start = preamble.find("struct Sphere {").unwrap();
+    let end = start + preamble[start..].find("}\n").unwrap() + 2;
+    // Fields of the type have to go too for the rest to compile, Sphere is
+    // checked first so their type doesn't matter
+    let without_sphere =
+        format!("{}{}", &preamble[..start], &preamble[end..]).replace(": Sphere,", ": vec4<f32>,");
+    assert!(without_sphere.contains("struct RayTracingParams {"));
+    let err = layout::check(&without_sphere).unwrap_err();
+    assert!(err.contains("Sphere isn't declared"), "{}", err);
+}
+
+#[test]
+fn includes_are_pasted_once() {
+    let source = shaders::compose("#include \"layout.wgsl\"\n#include \"layout.wgsl\"\n").unwrap();
+    assert_eq!(source.matches("struct Sphere").count(), 1);
+}
+
+#[test]
+fn defines_replace_whole_identifiers() {
+    let source = shaders::compose(
+        "#define SIZE 4u\n#define AREA SIZE * SIZE\nlet a = AREA + SIZE_X + 1e5 + TILE_SIZE;",
+    )
+    .unwrap();
+    assert_eq!(
+        source,
+        format!("let a = 4u * 4u + SIZE_X + 1e5 + {};\n", shaders::TILE_SIZE)
+    );
+}
+
+#[test]
+fn unknown_directives_and_includes_are_errors() {
+    assert!(shaders::compose("#pragma once").is_err());
+    assert!(shaders::compose("#include \"missing.wgsl\"").is_err());
+    assert!(shaders::compose("#include missing.wgsl").is_err());
+}
@@ -0,0 +1,111 @@
+// Runs SphereSnapshot against buffers on the software fallback adapter, standing
+// in for the physics pass by writing a known sphere into the buffer each frame
+#[allow(dead_code)]
+#[path = "../src/layout.rs"]
+mod layout;
+#[allow(dead_code)]
+#[path = "../src/readback.rs"]
+mod readback;
+#[allow(dead_code)]
+#[path = "../src/snapshot.rs"]
+mod snapshot;
+
+use layout::Sphere;
+use pollster::FutureExt as _;
+use snapshot::{SphereSnapshot, SNAPSHOT_INTERVAL};
+use std::{env, mem};
+
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    spheres_buffer: wgpu::Buffer,
+}
+
+impl Gpu {
+    // None when the GPU tests are skipped
+    fn new() -> Option<Self> {
+        if env::var_os("WROOM_SKIP_GPU_TESTS").is_some() {
+            eprintln!("Skipping the snapshot tests, WROOM_SKIP_GPU_TESTS is set");
+            return None;
+        }
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                force_fallback_adapter: true,
+                compatible_surface: None,
+            })
+            .block_on()
+            .expect(
+                "Can't find a fallback adapter, set WROOM_SKIP_GPU_TESTS=1 to skip the GPU tests",
+            );
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor::default(), None)
+            .block_on()
+            .expect("Can't create a device on the fallback adapter");
+        let spheres_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: mem::size_of::<Sphere>() as _,
+            usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Some(Gpu {
+            device,
+            queue,
+            spheres_buffer,
+        })
+    }
+
+    // A frame whose sphere is at x = frame, returns what `copy` returned.
+    // Nothing is waited for, so readbacks stay in flight until `wait`.
+    fn frame(&self, snapshot: &mut SphereSnapshot, frame: u32) -> bool {
+        self.queue.write_buffer(
+            &self.spheres_buffer,
+            0,
+            bytemuck::cast_slice(&[sphere_at(frame)]),
+        );
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let requested = snapshot.copy(&mut encoder, &self.spheres_buffer, 1, frame);
+        self.queue.submit(Some(encoder.finish()));
+        snapshot.start_readback();
+        requested
+    }
+
+    fn wait(&self) {
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+}
+
+fn sphere_at(frame: u32) -> Sphere {
+    Sphere {
+        pos: [frame as f32, 0.0, 8.0],
+        r: 0.5,
+        vel: [0.0; 4],
+        color: [0.5, 0.5, 0.5, 0.0],
+    }
+}
+
+#[test]
+fn requested_copy_is_not_held_back_by_a_periodic_one() {
+    let Some(gpu) = Gpu::new() else {
+        return;
+    };
+    let mut snapshot = SphereSnapshot::new(&gpu.device, vec![sphere_at(0)]);
+    for frame in 1..SNAPSHOT_INTERVAL {
+        assert!(!gpu.frame(&mut snapshot, frame));
+    }
+    // The periodic copy is encoded and left in flight
+    assert!(!gpu.frame(&mut snapshot, SNAPSHOT_INTERVAL));
+    snapshot.request();
+    let frame = SNAPSHOT_INTERVAL + 1;
+    assert!(
+        gpu.frame(&mut snapshot, frame),
+        "The requested copy waited for the periodic one"
+    );
+    gpu.wait();
+    assert!(snapshot.poll());
+    assert_eq!(snapshot.frame(), frame);
+    assert_eq!(snapshot.spheres()[0].pos, sphere_at(frame).pos);
+}